@@ -0,0 +1,378 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    api::*,
+    error::Error,
+    message::Message,
+};
+
+/// Parameters of an action, sent with [`OneBotApi::call`].
+pub trait Action: Serialize + Send + Sync {
+    /// Name of the action, without `_async` or `_rate_limited`.
+    const NAME: &'static str;
+    /// `data` of the reply.
+    type Output: DeserializeOwned + Default + Send;
+}
+/// Call `A::NAME` with `params` and parse the reply.
+pub(crate) async fn call<S: OneBotApi + ?Sized, A: Action>(api: &S, params: &A) -> Result<ActionResponse<A::Output>, Error> {
+    let params = serde_json::to_value(params)?;
+    api.call_action(A::NAME, params).await?.into_typed()
+}
+macro_rules! make_action {
+    (
+        $(#[$meta:meta])*
+        $struct_name:ident => $action:literal -> $output:ty {
+            $(
+            $(#[$field_meta:meta])*
+            $field_name:ident : $field_type:ty
+            ),*$(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(serde::Serialize,serde::Deserialize,Debug,Clone)]
+        pub struct $struct_name{
+            $(
+            $(#[$field_meta])*
+            pub $field_name : $field_type,
+            )*
+        }
+        impl $crate::action::Action for $struct_name{
+            const NAME: &'static str = $action;
+            type Output = $output;
+        }
+    };
+}
+make_action! {
+    /// Parameters of `send_private_msg`.
+    SendPrivateMsgParams => "send_private_msg" -> MessageId {
+        user_id:i64,
+        message:Message,
+        auto_escape:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `send_group_msg`.
+    SendGroupMsgParams => "send_group_msg" -> MessageId {
+        group_id:i64,
+        message:Message,
+        auto_escape:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_msg`.
+    GetMsgParams => "get_msg" -> GetMsgResult {
+        message_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_kick`.
+    SetGroupKickParams => "set_group_kick" -> Empty {
+        group_id:i64,
+        user_id:i64,
+        reject_add_request:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_ban`.
+    SetGroupBanParams => "set_group_ban" -> Empty {
+        group_id:i64,
+        user_id:i64,
+        duration:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_anonymous_ban`.
+    SetGroupAnonymousBanParams => "set_group_anonymous_ban" -> Empty {
+        group_id:i64,
+        anonymous_flag:String,
+        duration:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_whole_ban`.
+    SetGroupWholeBanParams => "set_group_whole_ban" -> Empty {
+        group_id:i64,
+        enable:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_admin`.
+    SetGroupAdminParams => "set_group_admin" -> Empty {
+        group_id:i64,
+        user_id:i64,
+        enable:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_anonymous`.
+    SetGroupAnonymousParams => "set_group_anonymous" -> Empty {
+        group_id:i64,
+        enable:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_card`.
+    SetGroupCardParams => "set_group_card" -> Empty {
+        group_id:i64,
+        user_id:i64,
+        card:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_name`.
+    SetGroupNameParams => "set_group_name" -> Empty {
+        group_id:i64,
+        group_name:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_leave`.
+    SetGroupLeaveParams => "set_group_leave" -> Empty {
+        group_id:i64,
+        is_dismiss:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_special_title`.
+    SetGroupSpecialTitleParams => "set_group_special_title" -> Empty {
+        group_id:i64,
+        user_id:i64,
+        special_title:String,
+        duration:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_friend_add_request`.
+    SetFriendAddRequestParams => "set_friend_add_request" -> Empty {
+        flag:String,
+        approve:bool,
+        remark:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_group_add_request`.
+    SetGroupAddRequestParams => "set_group_add_request" -> Empty {
+        flag:String,
+        sub_type:String,
+        approve:bool,
+        reason:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_login_info`.
+    GetLoginInfoParams => "get_login_info" -> LoginInfo {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_stranger_info`.
+    GetStrangerInfoParams => "get_stranger_info" -> StrangerInfo {
+        user_id:i64,
+        no_cache:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_friend_list`.
+    GetFriendListParams => "get_friend_list" -> Vec<FriendInfo> {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_info`.
+    GetGroupInfoParams => "get_group_info" -> GroupInfo {
+        group_id:i64,
+        no_cache:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_list`.
+    GetGroupListParams => "get_group_list" -> Vec<GroupInfo> {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_member_info`.
+    GetGroupMemberInfoParams => "get_group_member_info" -> GroupMemberInfo {
+        group_id:i64,
+        user_id:i64,
+        no_cache:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_member_list`.
+    GetGroupMemberListParams => "get_group_member_list" -> Vec<GroupMemberInfo> {
+        group_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_honor_info`.
+    GetGroupHonorInfoParams => "get_group_honor_info" -> HonorInfo {
+        group_id:i64,
+        #[serde(rename = "type")]
+        honor_type:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `delete_msg`.
+    DeleteMsgParams => "delete_msg" -> Empty {
+        message_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `send_like`.
+    SendLikeParams => "send_like" -> Empty {
+        user_id:i64,
+        times:u8,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_forward_msg`.
+    GetForwardMsgParams => "get_forward_msg" -> ForwardMsg {
+        id:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_image`.
+    GetImageParams => "get_image" -> MediaFile {
+        file:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_record`.
+    GetRecordParams => "get_record" -> MediaFile {
+        file:String,
+        out_format:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `can_send_image`.
+    CanSendImageParams => "can_send_image" -> CanSend {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `can_send_record`.
+    CanSendRecordParams => "can_send_record" -> CanSend {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_status`.
+    GetStatusParams => "get_status" -> BotStatus {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_version_info`.
+    GetVersionInfoParams => "get_version_info" -> VersionInfo {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_restart`.
+    SetRestartParams => "set_restart" -> Empty {
+        delay:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `clean_cache`.
+    CleanCacheParams => "clean_cache" -> Empty {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_cookies`.
+    GetCookiesParams => "get_cookies" -> Cookies {
+        domain:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_csrf_token`.
+    GetCsrfTokenParams => "get_csrf_token" -> CsrfToken {
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_credentials`.
+    GetCredentialsParams => "get_credentials" -> Credentials {
+        domain:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `send_discuss_msg`.
+    SendDiscussMsgParams => "send_discuss_msg" -> MessageId {
+        discuss_id:i64,
+        message:Message,
+        auto_escape:bool,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `send_msg`, the target is picked by `message_type`.
+    SendMsgParams => "send_msg" -> MessageId {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message_type:Option<MsgType>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user_id:Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        group_id:Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        discuss_id:Option<i64>,
+        message:Message,
+        auto_escape:bool,
+        self_id:i64,
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_params() {
+        let params = GetGroupHonorInfoParams {
+            group_id: 1,
+            honor_type: "all".to_string(),
+            self_id: 2,
+        };
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(json, serde_json::json!({"group_id":1,"type":"all","self_id":2}));
+        let params = SendMsgParams {
+            message_type: Some(MsgType::Group),
+            user_id: None,
+            group_id: Some(1),
+            discuss_id: None,
+            message: "hi".into(),
+            auto_escape: false,
+            self_id: 2,
+        };
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(json, serde_json::json!({"message_type":"group","group_id":1,"message":[{"type":"text","data":{"text":"hi"}}],"auto_escape":false,"self_id":2}));
+    }
+}
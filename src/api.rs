@@ -1,95 +1,1358 @@
-use reqwest::{ Response,Body};
-use crate::error::Error;
-/// use HTTP to call Onebot API
-#[derive(Default)]
-pub struct HttpApi {
-    api_root: String,
-    access_token: Option<String>,
-    timeout: Option<u64>,
-    //action: String,
+use std::{collections::HashMap, fmt, path::Path, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use reqwest::{header::CONTENT_TYPE, Body, Method, RequestBuilder, Response};
+use serde::{de::{DeserializeOwned, IgnoredAny}, Deserialize, Serialize};
+use serde_json::Value;
+use crate::{
+    action::{self, *},
+    error::Error,
+    event::{Role, Sender},
+    limit::{RateLimit, RateLimiter, Target},
+    message::{Message, MessageFormat, MessageSegment},
+    retry::RetryPolicy,
+};
+/// The reply to an Onebot action.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActionResponse<T = Value> {
+    pub status: String,
+    pub retcode: i64,
+    #[serde(default)]
+    pub data: T,
+    #[serde(default)]
+    pub echo: Option<Value>,
+    /// Set by [`WithMode`] when the call was meant to be queued, so retcode 1 is not an error.
+    #[serde(skip)]
+    queued: bool,
 }
-impl HttpApi {
-    pub fn new(api_root: &str, access_token: Option<String>, timeout: Option<u64>) -> HttpApi {
-        HttpApi {
-            api_root: api_root.to_string(),
-            access_token,
-            timeout,
+impl ActionResponse {
+    /// Turn a non-zero `retcode` into an [`Error`] and parse `data` as `T`.
+    ///
+    /// A call queued by [`CallMode::Async`] or [`CallMode::RateLimited`] has no `data`,
+    /// it is left at `T::default()`.
+    pub fn into_typed<T: DeserializeOwned + Default>(self) -> Result<ActionResponse<T>, Error> {
+        match self.retcode {
+            0 => {}
+            1 if self.queued => return Ok(self.map(|_| T::default())),
+            1 => return Err(Error::Async),
+            1400 => return Err(Error::BadRequest),
+            1401 => return Err(Error::Unauthorized),
+            1403 => return Err(Error::Forbidden),
+            1404 => return Err(Error::ActionNotFound),
+            retcode => return Err(Error::ActionFailed(retcode)),
         }
+        let data = serde_json::from_value(self.data.clone())?;
+        Ok(self.map(|_| data))
     }
-    pub async fn call_actions<T:Into<Body>>(&self,action: &str,data:T) -> Result<Response, Error> {
-        let client = if let Some(timeout) =self.timeout  {
-            reqwest::ClientBuilder::new().timeout(std::time::Duration::from_secs(timeout)).build()?
-        }else{
-            reqwest::Client::new()
+}
+impl<T> ActionResponse<T> {
+    /// Whether the action was queued by [`CallMode::Async`] or [`CallMode::RateLimited`]
+    /// rather than run, in which case `data` is only a default.
+    pub fn is_queued(&self) -> bool {
+        self.queued && self.retcode == 1
+    }
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ActionResponse<U> {
+        ActionResponse {
+            status: self.status,
+            retcode: self.retcode,
+            data: f(self.data),
+            echo: self.echo,
+            queued: self.queued,
+        }
+    }
+}
+/// `data` of the actions that reply with nothing, whatever the implementation puts there.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Empty;
+impl<'de> Deserialize<'de> for Empty {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IgnoredAny::deserialize(deserializer)?;
+        Ok(Empty)
+    }
+}
+/// `data` of the actions that send a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MessageId {
+    pub message_id: i64,
+}
+/// `data` of `get_msg`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GetMsgResult {
+    pub time: i64,
+    pub message_type: String,
+    pub message_id: i64,
+    pub real_id: i64,
+    pub sender: Sender,
+    pub message: Message,
+}
+/// `data` of `get_login_info`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LoginInfo {
+    pub user_id: i64,
+    pub nickname: String,
+}
+/// `data` of `get_stranger_info`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StrangerInfo {
+    pub user_id: i64,
+    pub nickname: String,
+    #[serde(default)]
+    pub sex: String,
+    #[serde(default)]
+    pub age: i32,
+}
+/// An entry of `get_friend_list`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FriendInfo {
+    pub user_id: i64,
+    pub nickname: String,
+    #[serde(default)]
+    pub remark: String,
+}
+/// `data` of `get_group_info`, an entry of `get_group_list`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GroupInfo {
+    pub group_id: i64,
+    pub group_name: String,
+    #[serde(default)]
+    pub member_count: i32,
+    #[serde(default)]
+    pub max_member_count: i32,
+}
+/// `data` of `get_group_member_info`, an entry of `get_group_member_list`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GroupMemberInfo {
+    pub group_id: i64,
+    pub user_id: i64,
+    pub nickname: String,
+    #[serde(default)]
+    pub card: String,
+    #[serde(default)]
+    pub sex: String,
+    #[serde(default)]
+    pub age: i32,
+    #[serde(default)]
+    pub area: String,
+    #[serde(default)]
+    pub join_time: i64,
+    #[serde(default)]
+    pub last_sent_time: i64,
+    #[serde(default)]
+    pub level: String,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub unfriendly: bool,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub title_expire_time: i64,
+    #[serde(default)]
+    pub card_changeable: bool,
+}
+/// A member listed in `get_group_honor_info`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HonorMember {
+    pub user_id: i64,
+    pub nickname: String,
+    #[serde(default)]
+    pub avatar: String,
+    #[serde(default)]
+    pub description: String,
+}
+/// The current dragon king of a group.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CurrentTalkative {
+    pub user_id: i64,
+    pub nickname: String,
+    #[serde(default)]
+    pub avatar: String,
+    #[serde(default)]
+    pub day_count: i32,
+}
+/// `data` of `get_group_honor_info`.
+/// Only the lists asked for by `type` are present.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HonorInfo {
+    pub group_id: i64,
+    #[serde(default)]
+    pub current_talkative: Option<CurrentTalkative>,
+    #[serde(default)]
+    pub talkative_list: Option<Vec<HonorMember>>,
+    #[serde(default)]
+    pub performer_list: Option<Vec<HonorMember>>,
+    #[serde(default)]
+    pub legend_list: Option<Vec<HonorMember>>,
+    #[serde(default)]
+    pub strong_newbie_list: Option<Vec<HonorMember>>,
+    #[serde(default)]
+    pub emotion_list: Option<Vec<HonorMember>>,
+}
+/// A message merged into a forward message, see `get_forward_msg`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ForwardNode {
+    pub user_id: i64,
+    pub nickname: String,
+    pub time: Option<i64>,
+    pub content: Message,
+}
+/// `data` of `get_forward_msg`, see [`GetForwardMsgParams`].
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(from = "RawForwardMsg")]
+pub struct ForwardMsg {
+    pub nodes: Vec<ForwardNode>,
+}
+/// `data` of `get_forward_msg`, as sent by Onebot v11 or by go-cqhttp.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawForwardMsg {
+    Onebot { message: Vec<OnebotForwardNode> },
+    GoCqhttp { messages: Vec<GoCqhttpForwardNode> },
+}
+#[derive(Deserialize)]
+struct OnebotForwardNode {
+    data: OnebotForwardNodeData,
+}
+#[derive(Deserialize)]
+struct OnebotForwardNodeData {
+    #[serde(deserialize_with = "de_i64_or_str")]
+    user_id: i64,
+    nickname: String,
+    content: Message,
+}
+#[derive(Deserialize)]
+struct GoCqhttpForwardNode {
+    sender: Sender,
+    time: i64,
+    content: Message,
+}
+impl From<RawForwardMsg> for ForwardMsg {
+    fn from(msg: RawForwardMsg) -> Self {
+        let nodes = match msg {
+            RawForwardMsg::Onebot { message } => message
+                .into_iter()
+                .map(|node| ForwardNode {
+                    user_id: node.data.user_id,
+                    nickname: node.data.nickname,
+                    time: None,
+                    content: node.data.content,
+                })
+                .collect(),
+            RawForwardMsg::GoCqhttp { messages } => messages
+                .into_iter()
+                .map(|node| ForwardNode {
+                    user_id: node.sender.user_id,
+                    nickname: node.sender.nickname,
+                    time: Some(node.time),
+                    content: node.content,
+                })
+                .collect(),
         };
-        let url = format!("{}/{}", self.api_root, action);
-        let res = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.access_token.clone().unwrap_or_default()))
-            .body(data)
-            .send()
-            .await?;
+        ForwardMsg { nodes }
+    }
+}
+/// Segment data carries numbers as strings.
+fn de_i64_or_str<'de, D: serde::Deserializer<'de>>(de: D) -> Result<i64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IntOrStr {
+        Int(i64),
+        Str(String),
+    }
+    match IntOrStr::deserialize(de)? {
+        IntOrStr::Int(i) => Ok(i),
+        IntOrStr::Str(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+/// `data` of `get_image` and `get_record`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MediaFile {
+    /// Local path of the file on the Onebot implementation's host.
+    pub file: String,
+}
+/// `data` of `can_send_image` and `can_send_record`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CanSend {
+    pub yes: bool,
+}
+/// Counters reported by go-cqhttp in `get_status`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Statistics {
+    pub packet_received: u64,
+    pub packet_sent: u64,
+    pub packet_lost: u64,
+    pub message_received: u64,
+    pub message_sent: u64,
+    pub disconnect_times: u64,
+    pub lost_times: u64,
+    pub last_message_time: i64,
+}
+/// `data` of `get_status`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BotStatus {
+    /// `None` when the implementation cannot tell whether the account is online.
+    pub online: Option<bool>,
+    pub good: bool,
+    #[serde(default)]
+    pub stat: Statistics,
+    /// Implementation specific fields.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+/// `data` of `get_version_info`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VersionInfo {
+    pub app_name: String,
+    #[serde(default)]
+    pub app_version: String,
+    #[serde(default)]
+    pub protocol_version: String,
+    /// Implementation specific fields.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+/// `data` of `get_cookies`.
+/// `Debug` hides the value so it never ends up in logs.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Cookies {
+    pub cookies: String,
+}
+impl fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cookies").field("cookies", &"<redacted>").finish()
+    }
+}
+/// `data` of `get_csrf_token`.
+/// `Debug` hides the value so it never ends up in logs.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CsrfToken {
+    pub token: i64,
+}
+impl fmt::Debug for CsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsrfToken").field("token", &"<redacted>").finish()
+    }
+}
+/// `data` of `get_credentials`.
+/// `Debug` hides the values so they never end up in logs.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Credentials {
+    pub cookies: String,
+    pub csrf_token: i64,
+}
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("cookies", &"<redacted>")
+            .field("csrf_token", &"<redacted>")
+            .finish()
+    }
+}
+/// How the Onebot implementation should run an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CallMode {
+    /// Wait for the action to finish.
+    #[default]
+    Normal,
+    /// Queue the action and return at once (`<action>_async`).
+    ///
+    /// The reply carries no `data`, so it is left at its default, e.g. a `message_id` of 0.
+    /// Check [`ActionResponse::is_queued`] before using it.
+    Async,
+    /// Queue the action behind the implementation's rate limiter (`<action>_rate_limited`).
+    ///
+    /// Like [`CallMode::Async`], `data` is left at its default.
+    RateLimited,
+}
+impl CallMode {
+    fn suffix(&self) -> &'static str {
+        match self {
+            CallMode::Normal => "",
+            CallMode::Async => "_async",
+            CallMode::RateLimited => "_rate_limited",
+        }
+    }
+}
+/// Calls every action of the wrapped api in a [`CallMode`], see [`OneBotApi::with_mode`].
+pub struct WithMode<'a, A: ?Sized> {
+    api: &'a A,
+    mode: CallMode,
+}
+#[async_trait]
+impl<A: OneBotApi + ?Sized> OneBotApi for WithMode<'_, A> {
+    async fn call_action(&self, action: &str, params: Value) -> Result<ActionResponse, Error> {
+        let action = format!("{}{}", action, self.mode.suffix());
+        let mut res = self.api.call_action(&action, params).await?;
+        res.queued = self.mode != CallMode::Normal;
         Ok(res)
     }
+}
+/// Onebot actions, independent of the transport they are sent over.
+///
+/// Implementors only provide [`OneBotApi::call_action`].
+#[async_trait]
+pub trait OneBotApi: Send + Sync {
+    /// Call `action` with `params` and return the raw reply, whatever its `retcode`.
+    async fn call_action(&self, action: &str, params: Value) -> Result<ActionResponse, Error>;
+    /// Make the next actions in `mode`, e.g. `api.with_mode(CallMode::Async).send_group_msg(..)`.
+    fn with_mode(&self, mode: CallMode) -> WithMode<'_, Self>
+    where
+        Self: Sized,
+    {
+        WithMode { api: self, mode }
+    }
+    /// Call the action `params` belong to, e.g. `api.call(&SendGroupMsgParams { .. })`.
+    async fn call<A: Action>(&self, params: &A) -> Result<ActionResponse<A::Output>, Error>
+    where
+        Self: Sized,
+    {
+        action::call(self, params).await
+    }
     /// 
     /// 发送私聊消息\
     /// 
     /// `user_id`:目标QQ\
-    /// `message`:消息内容\  
-    /// `auto_escape`:消息内容是否作为纯文本发送（即不解析 CQ 码)
-    /// `self_id`:机器人QQ
-    pub async fn send_private_msg<T:ToString>(&self,user_id:i64,message:T,auto_space:bool,self_id:i64)->Result<Response,Error>{
-        let auto_space=auto_space as u8; 
-        let json=serde_json::json!({
-            "user_id":user_id,
-            "message":message.to_string(),
-            "auto_escape":auto_space,
-            "self_id":self_id
-        });
-        let data=serde_json::to_string(&json).unwrap();
-        self.call_actions("send_private_msg",data).await
+    /// `message`:消息内容，字符串会作为纯文本发送，CQ 码字符串请先用 [`Message::from_cq_str`] 解析\
+    /// `auto_escape`:仅在 [`MessageFormat::String`] 下有效，消息内容是否作为纯文本发送（即不解析 CQ 码)\
+    /// `self_id`:机器人QQ
+    async fn send_private_msg<T:Into<Message>+Send>(&self,user_id:i64,message:T,auto_space:bool,self_id:i64)->Result<ActionResponse<MessageId>,Error>
+    where
+        Self: Sized,
+    {
+        action::call(self,&SendPrivateMsgParams{
+            user_id,
+            message:message.into(),
+            auto_escape:auto_space,
+            self_id,
+        }).await
     }
     /// 
     /// 发送群消息\
     /// `group_id`:目标群\
-    /// `message`:消息内容\
-    /// `auto_escape`:消息内容是否作为纯文本发送（即不解析 CQ 码)
-    /// `self_id`:机器人QQ
-    pub async fn send_group_msg<T:ToString>(&self,group_id:i64,message:T,auto_space:bool,self_id:i64)->Result<Response,Error>{
-        let json=serde_json::json!({
-            "group_id":group_id,
-            "message":message.to_string(),
-            "auto_escape":auto_space,
-            "self_id":self_id
-        });
-        let data=serde_json::to_string(&json).unwrap();
-        self.call_actions("send_group_msg",data).await
+    /// `message`:消息内容，字符串会作为纯文本发送，CQ 码字符串请先用 [`Message::from_cq_str`] 解析\
+    /// `auto_escape`:仅在 [`MessageFormat::String`] 下有效，消息内容是否作为纯文本发送（即不解析 CQ 码)\
+    /// `self_id`:机器人QQ
+    async fn send_group_msg<T:Into<Message>+Send>(&self,group_id:i64,message:T,auto_space:bool,self_id:i64)->Result<ActionResponse<MessageId>,Error>
+    where
+        Self: Sized,
+    {
+        action::call(self,&SendGroupMsgParams{
+            group_id,
+            message:message.into(),
+            auto_escape:auto_space,
+            self_id,
+        }).await
     }
     ///
-    /// 获取消息的真实ID\
+    /// 获取消息\
     /// `message_id`:消息ID\
     /// `self_id`:机器人QQ
-    pub async fn get_msg(&self,msg_id:i64,self_id:i64)->Result<Response,Error>{
-        let json=serde_json::json!({
-            "message_id":msg_id,
-            "self_id":self_id
-        });
-        let data=serde_json::to_string(&json).unwrap();
-        self.call_actions("get_msg",data).await
+    async fn get_msg(&self,msg_id:i64,self_id:i64)->Result<ActionResponse<GetMsgResult>,Error>{
+        action::call(self,&GetMsgParams{
+            message_id:msg_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组踢人\
+    /// `group_id`:群号\
+    /// `user_id`:要踢的QQ号\
+    /// `reject_add_request`:拒绝此人的加群请求\
+    /// `self_id`:机器人QQ
+    async fn set_group_kick(&self,group_id:i64,user_id:i64,reject_add_request:bool,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupKickParams{
+            group_id,
+            user_id,
+            reject_add_request,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组单人禁言\
+    /// `group_id`:群号\
+    /// `user_id`:要禁言的QQ号\
+    /// `duration`:禁言时长，单位秒，0 表示取消禁言\
+    /// `self_id`:机器人QQ
+    async fn set_group_ban(&self,group_id:i64,user_id:i64,duration:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupBanParams{
+            group_id,
+            user_id,
+            duration,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组匿名用户禁言\
+    /// `group_id`:群号\
+    /// `anonymous_flag`:群消息事件中匿名用户的 `flag`\
+    /// `duration`:禁言时长，单位秒，无法取消匿名用户禁言\
+    /// `self_id`:机器人QQ
+    async fn set_group_anonymous_ban(&self,group_id:i64,anonymous_flag:&str,duration:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupAnonymousBanParams{
+            group_id,
+            anonymous_flag:anonymous_flag.to_string(),
+            duration,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组全员禁言\
+    /// `group_id`:群号\
+    /// `enable`:是否禁言\
+    /// `self_id`:机器人QQ
+    async fn set_group_whole_ban(&self,group_id:i64,enable:bool,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupWholeBanParams{
+            group_id,
+            enable,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组设置管理员\
+    /// `group_id`:群号\
+    /// `user_id`:要设置管理员的QQ号\
+    /// `enable`:true 为设置，false 为取消\
+    /// `self_id`:机器人QQ
+    async fn set_group_admin(&self,group_id:i64,user_id:i64,enable:bool,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupAdminParams{
+            group_id,
+            user_id,
+            enable,
+            self_id,
+        }).await
+    }
+    ///
+    /// 群组匿名\
+    /// `group_id`:群号\
+    /// `enable`:是否允许匿名聊天\
+    /// `self_id`:机器人QQ
+    async fn set_group_anonymous(&self,group_id:i64,enable:bool,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupAnonymousParams{
+            group_id,
+            enable,
+            self_id,
+        }).await
+    }
+    ///
+    /// 设置群名片（群备注）\
+    /// `group_id`:群号\
+    /// `user_id`:要设置的QQ号\
+    /// `card`:群名片内容，空字符串表示删除群名片\
+    /// `self_id`:机器人QQ
+    async fn set_group_card(&self,group_id:i64,user_id:i64,card:&str,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupCardParams{
+            group_id,
+            user_id,
+            card:card.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 设置群名\
+    /// `group_id`:群号\
+    /// `group_name`:新群名\
+    /// `self_id`:机器人QQ
+    async fn set_group_name(&self,group_id:i64,group_name:&str,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupNameParams{
+            group_id,
+            group_name:group_name.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 退出群组\
+    /// `group_id`:群号\
+    /// `is_dismiss`:是否解散，如果登录号是群主，则仅在此项为 true 时能够解散\
+    /// `self_id`:机器人QQ
+    async fn set_group_leave(&self,group_id:i64,is_dismiss:bool,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupLeaveParams{
+            group_id,
+            is_dismiss,
+            self_id,
+        }).await
+    }
+    ///
+    /// 设置群组专属头衔\
+    /// `group_id`:群号\
+    /// `user_id`:要设置的QQ号\
+    /// `special_title`:专属头衔，空字符串表示删除专属头衔\
+    /// `duration`:专属头衔有效期，单位秒，-1 表示永久\
+    /// `self_id`:机器人QQ
+    async fn set_group_special_title(&self,group_id:i64,user_id:i64,special_title:&str,duration:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupSpecialTitleParams{
+            group_id,
+            user_id,
+            special_title:special_title.to_string(),
+            duration,
+            self_id,
+        }).await
+    }
+    ///
+    /// 处理加好友请求\
+    /// `flag`:加好友请求的 flag（需从上报的数据中获得）\
+    /// `approve`:是否同意请求\
+    /// `remark`:添加后的好友备注（仅在同意时有效）\
+    /// `self_id`:机器人QQ
+    async fn set_friend_add_request(&self,flag:&str,approve:bool,remark:&str,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetFriendAddRequestParams{
+            flag:flag.to_string(),
+            approve,
+            remark:remark.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 处理加群请求／邀请\
+    /// `flag`:加群请求的 flag（需从上报的数据中获得）\
+    /// `sub_type`:`add` 或 `invite`，请求类型（需要和上报消息中的 `sub_type` 字段相符）\
+    /// `approve`:是否同意请求／邀请\
+    /// `reason`:拒绝理由（仅在拒绝时有效）\
+    /// `self_id`:机器人QQ
+    async fn set_group_add_request(&self,flag:&str,sub_type:&str,approve:bool,reason:&str,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetGroupAddRequestParams{
+            flag:flag.to_string(),
+            sub_type:sub_type.to_string(),
+            approve,
+            reason:reason.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取登录号信息\
+    /// `self_id`:机器人QQ
+    async fn get_login_info(&self,self_id:i64)->Result<ActionResponse<LoginInfo>,Error>{
+        action::call(self,&GetLoginInfoParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取陌生人信息\
+    /// `user_id`:QQ号\
+    /// `no_cache`:是否不使用缓存\
+    /// `self_id`:机器人QQ
+    async fn get_stranger_info(&self,user_id:i64,no_cache:bool,self_id:i64)->Result<ActionResponse<StrangerInfo>,Error>{
+        action::call(self,&GetStrangerInfoParams{
+            user_id,
+            no_cache,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取好友列表\
+    /// `self_id`:机器人QQ
+    async fn get_friend_list(&self,self_id:i64)->Result<ActionResponse<Vec<FriendInfo>>,Error>{
+        action::call(self,&GetFriendListParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群信息\
+    /// `group_id`:群号\
+    /// `no_cache`:是否不使用缓存\
+    /// `self_id`:机器人QQ
+    async fn get_group_info(&self,group_id:i64,no_cache:bool,self_id:i64)->Result<ActionResponse<GroupInfo>,Error>{
+        action::call(self,&GetGroupInfoParams{
+            group_id,
+            no_cache,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群列表\
+    /// `self_id`:机器人QQ
+    async fn get_group_list(&self,self_id:i64)->Result<ActionResponse<Vec<GroupInfo>>,Error>{
+        action::call(self,&GetGroupListParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群成员信息\
+    /// `group_id`:群号\
+    /// `user_id`:QQ号\
+    /// `no_cache`:是否不使用缓存\
+    /// `self_id`:机器人QQ
+    async fn get_group_member_info(&self,group_id:i64,user_id:i64,no_cache:bool,self_id:i64)->Result<ActionResponse<GroupMemberInfo>,Error>{
+        action::call(self,&GetGroupMemberInfoParams{
+            group_id,
+            user_id,
+            no_cache,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群成员列表\
+    /// `group_id`:群号\
+    /// `self_id`:机器人QQ
+    async fn get_group_member_list(&self,group_id:i64,self_id:i64)->Result<ActionResponse<Vec<GroupMemberInfo>>,Error>{
+        action::call(self,&GetGroupMemberListParams{
+            group_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群荣誉信息\
+    /// `group_id`:群号\
+    /// `honor_type`:`talkative` `performer` `legend` `strong_newbie` `emotion` 或 `all`\
+    /// `self_id`:机器人QQ
+    async fn get_group_honor_info(&self,group_id:i64,honor_type:&str,self_id:i64)->Result<ActionResponse<HonorInfo>,Error>{
+        action::call(self,&GetGroupHonorInfoParams{
+            group_id,
+            honor_type:honor_type.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 撤回消息\
+    /// `message_id`:消息ID\
+    /// `self_id`:机器人QQ
+    async fn delete_msg(&self,message_id:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&DeleteMsgParams{
+            message_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 发送好友赞\
+    /// `user_id`:对方QQ号\
+    /// `times`:赞的次数，每个好友每天最多 10 次\
+    /// `self_id`:机器人QQ
+    async fn send_like(&self,user_id:i64,times:u8,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SendLikeParams{
+            user_id,
+            times,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取合并转发消息\
+    /// `id`:合并转发 ID\
+    /// `self_id`:机器人QQ
+    async fn get_forward_msg(&self,id:&str,self_id:i64)->Result<ActionResponse<Vec<ForwardNode>>,Error>{
+        let res=action::call(self,&GetForwardMsgParams{
+            id:id.to_string(),
+            self_id,
+        }).await?;
+        Ok(res.map(|msg|msg.nodes))
+    }
+    ///
+    /// 获取图片\
+    /// `file`:收到的图片文件名（消息段的 `file` 参数）\
+    /// `self_id`:机器人QQ
+    async fn get_image(&self,file:&str,self_id:i64)->Result<ActionResponse<MediaFile>,Error>{
+        action::call(self,&GetImageParams{
+            file:file.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取语音\
+    /// `file`:收到的语音文件名（消息段的 `file` 参数）\
+    /// `out_format`:要转换到的格式，目前支持 `mp3`、`amr`、`wma`、`m4a`、`spx`、`ogg`、`wav`、`flac`\
+    /// `self_id`:机器人QQ
+    async fn get_record(&self,file:&str,out_format:&str,self_id:i64)->Result<ActionResponse<MediaFile>,Error>{
+        action::call(self,&GetRecordParams{
+            file:file.to_string(),
+            out_format:out_format.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 检查是否可以发送图片\
+    /// `self_id`:机器人QQ
+    async fn can_send_image(&self,self_id:i64)->Result<ActionResponse<CanSend>,Error>{
+        action::call(self,&CanSendImageParams{
+            self_id,
+        }).await
     }
-    pub async fn send_msg(&self,msg_type:MsgType,group_id:i64,user_id:i64,message:String,auto_space:bool,self_id:i64)->Result<Response,Error>{
-        match msg_type{
-            MsgType::Private=>self.send_private_msg(user_id,message,auto_space,self_id).await,
-            MsgType::Group=>self.send_group_msg(group_id,message,auto_space,self_id).await,
-            MsgType::Discuss=>unimplemented!(),
+    ///
+    /// 检查是否可以发送语音\
+    /// `self_id`:机器人QQ
+    async fn can_send_record(&self,self_id:i64)->Result<ActionResponse<CanSend>,Error>{
+        action::call(self,&CanSendRecordParams{
+            self_id,
+        }).await
+    }
+    /// Save a received image segment to `path`.
+    ///
+    /// Downloads the segment's `url` with `client` when there is one, e.g. [`HttpApi::client`],
+    /// otherwise copies the file returned by `get_image`, which only works when the Onebot
+    /// implementation runs on the same host.
+    async fn save_image(&self,client:&reqwest::Client,segment:&MessageSegment,path:&Path,self_id:i64)->Result<(),Error>{
+        if segment.name()!="image"{
+            return Err(Error::SegmentType("image"));
         }
+        let data=segment.data();
+        if let Some(url)=data.get("url"){
+            let bytes=client.get(url).send().await?.error_for_status()?.bytes().await?;
+            tokio::fs::write(path,bytes).await?;
+            return Ok(());
+        }
+        let file=data.get("file").ok_or(Error::MissingData("file"))?;
+        let image=self.get_image(file,self_id).await?;
+        tokio::fs::copy(&image.data.file,path).await?;
+        Ok(())
+    }
+    ///
+    /// 获取运行状态\
+    /// `self_id`:机器人QQ
+    async fn get_status(&self,self_id:i64)->Result<ActionResponse<BotStatus>,Error>{
+        action::call(self,&GetStatusParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取版本信息\
+    /// `self_id`:机器人QQ
+    async fn get_version_info(&self,self_id:i64)->Result<ActionResponse<VersionInfo>,Error>{
+        action::call(self,&GetVersionInfoParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 重启 Onebot 实现\
+    /// `delay`:要延迟的毫秒数\
+    /// `self_id`:机器人QQ
+    async fn set_restart(&self,delay:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetRestartParams{
+            delay,
+            self_id,
+        }).await
+    }
+    ///
+    /// 清理缓存\
+    /// `self_id`:机器人QQ
+    async fn clean_cache(&self,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&CleanCacheParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取 Cookies\
+    /// `domain`:需要获取 cookies 的域名\
+    /// `self_id`:机器人QQ
+    async fn get_cookies(&self,domain:&str,self_id:i64)->Result<ActionResponse<Cookies>,Error>{
+        action::call(self,&GetCookiesParams{
+            domain:domain.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取 CSRF Token\
+    /// `self_id`:机器人QQ
+    async fn get_csrf_token(&self,self_id:i64)->Result<ActionResponse<CsrfToken>,Error>{
+        action::call(self,&GetCsrfTokenParams{
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取 QQ 相关接口凭证，即 `get_cookies` 和 `get_csrf_token` 的合并\
+    /// `domain`:需要获取 cookies 的域名\
+    /// `self_id`:机器人QQ
+    async fn get_credentials(&self,domain:&str,self_id:i64)->Result<ActionResponse<Credentials>,Error>{
+        action::call(self,&GetCredentialsParams{
+            domain:domain.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 发送讨论组消息\
+    /// `discuss_id`:目标讨论组\
+    /// `message`:消息内容，字符串会作为纯文本发送，CQ 码字符串请先用 [`Message::from_cq_str`] 解析\
+    /// `auto_escape`:仅在 [`MessageFormat::String`] 下有效，消息内容是否作为纯文本发送（即不解析 CQ 码)\
+    /// `self_id`:机器人QQ
+    async fn send_discuss_msg<T:Into<Message>+Send>(&self,discuss_id:i64,message:T,auto_space:bool,self_id:i64)->Result<ActionResponse<MessageId>,Error>
+    where
+        Self: Sized,
+    {
+        action::call(self,&SendDiscussMsgParams{
+            discuss_id,
+            message:message.into(),
+            auto_escape:auto_space,
+            self_id,
+        }).await
+    }
+    ///
+    /// 发送消息\
+    /// `msg_type`:消息类型\
+    /// `group_id`:目标群，`msg_type` 为讨论组时为目标讨论组\
+    /// `user_id`:目标QQ，仅私聊时使用\
+    /// `message`:消息内容，字符串会作为纯文本发送，CQ 码字符串请先用 [`Message::from_cq_str`] 解析\
+    /// `auto_escape`:仅在 [`MessageFormat::String`] 下有效，消息内容是否作为纯文本发送（即不解析 CQ 码)\
+    /// `self_id`:机器人QQ
+    async fn send_msg<T:Into<Message>+Send>(&self,msg_type:MsgType,group_id:i64,user_id:i64,message:T,auto_space:bool,self_id:i64)->Result<ActionResponse<MessageId>,Error>
+    where
+        Self: Sized,
+    {
+        let (user_id,group_id,discuss_id)=match msg_type{
+            MsgType::Private=>(Some(user_id),None,None),
+            MsgType::Group=>(None,Some(group_id),None),
+            MsgType::Discuss=>(None,None,Some(group_id)),
+        };
+        action::call(self,&SendMsgParams{
+            message_type:Some(msg_type),
+            user_id,
+            group_id,
+            discuss_id,
+            message:message.into(),
+            auto_escape:auto_space,
+            self_id,
+        }).await
     }
 }
+#[async_trait]
+impl<A: OneBotApi + ?Sized> OneBotApi for Arc<A> {
+    async fn call_action(&self, action: &str, params: Value) -> Result<ActionResponse, Error> {
+        (**self).call_action(action, params).await
+    }
+}
+/// How [`HttpApi`] passes action parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequestStyle {
+    /// POST with a JSON body.
+    #[default]
+    PostJson,
+    /// POST with an `application/x-www-form-urlencoded` body.
+    PostForm,
+    /// GET with the parameters in the query string.
+    GetQuery,
+}
+/// Where [`HttpApi`] puts the access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TokenPlacement {
+    /// `Authorization: Bearer <token>` header.
+    #[default]
+    Header,
+    /// `access_token` query parameter.
+    Query,
+}
+/// use HTTP to call Onebot API
+#[derive(Default, Clone)]
+pub struct HttpApi {
+    api_root: String,
+    access_token: Option<String>,
+    request_style: RequestStyle,
+    token_placement: TokenPlacement,
+    client: reqwest::Client,
+    limiter: Option<Arc<RateLimiter>>,
+    retry: Option<RetryPolicy>,
+    message_format: MessageFormat,
+}
+impl HttpApi {
+    /// # Panics
+    /// Panics if the HTTP client cannot be built, see [`HttpApiBuilder::build`].
+    pub fn new(api_root: &str, access_token: Option<String>, timeout: Option<u64>) -> HttpApi {
+        let mut builder = HttpApi::builder(api_root);
+        if let Some(token) = access_token {
+            builder = builder.access_token(&token);
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder.build().expect("failed to build HTTP client")
+    }
+    pub fn builder(api_root: &str) -> HttpApiBuilder {
+        HttpApiBuilder {
+            api_root: api_root.to_string(),
+            ..Default::default()
+        }
+    }
+    /// The pooled HTTP client, configured by [`HttpApiBuilder`].
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+    /// POST `data` to `action` as is.
+    pub async fn call_actions<T:Into<Body>>(&self,action: &str,data:T) -> Result<Response, Error> {
+        self.call_actions_with_mode(action, CallMode::Normal, data).await
+    }
+    /// POST `data` to `action` in `mode`, e.g. to `send_group_msg_async` for [`CallMode::Async`].
+    pub async fn call_actions_with_mode<T:Into<Body>>(&self,action: &str,mode: CallMode,data:T) -> Result<Response, Error> {
+        let action = format!("{}{}", action, mode.suffix());
+        let res = self.request(Method::POST, &action)
+            .body(data)
+            .send()
+            .await?;
+        Ok(res)
+    }
+    /// A request to `action` carrying the access token, if there is one.
+    fn request(&self, method: Method, action: &str) -> RequestBuilder {
+        let url = format!("{}/{}", self.api_root, action);
+        let req = self.client.request(method, &url);
+        match (&self.access_token, self.token_placement) {
+            (None, _) => req,
+            (Some(token), TokenPlacement::Header) => req.bearer_auth(token),
+            (Some(token), TokenPlacement::Query) => req.query(&[("access_token", token)]),
+        }
+    }
+    async fn call_action_once(&self, action: &str, params: &Value) -> Result<ActionResponse, Error> {
+        let res = match self.request_style {
+            RequestStyle::PostJson => {
+                let data=serde_json::to_string(params)?;
+                self.request(Method::POST, action)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(data)
+            }
+            RequestStyle::PostForm => self.request(Method::POST, action).form(&form_pairs(params)),
+            RequestStyle::GetQuery => self.request(Method::GET, action).query(&form_pairs(params)),
+        };
+        let res=res.send().await?;
+        match res.status().as_u16() {
+            400 => return Err(Error::BadRequest),
+            401 => return Err(Error::Unauthorized),
+            403 => return Err(Error::Forbidden),
+            404 => return Err(Error::ActionNotFound),
+            status @ 500.. => return Err(Error::ServerError(status)),
+            _ => {}
+        }
+        let body=res.text().await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+/// Flatten an object of parameters into key-value pairs, nested values stay JSON.
+fn form_pairs(params: &Value) -> Vec<(String, String)> {
+    let Some(params) = params.as_object() else {
+        return Vec::new();
+    };
+    params
+        .iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| match v {
+            Value::String(s) => (k.clone(), s.clone()),
+            v => (k.clone(), v.to_string()),
+        })
+        .collect()
+}
+/// Configure the HTTP client shared by every action of a [`HttpApi`].
+#[derive(Default)]
+pub struct HttpApiBuilder {
+    api_root: String,
+    access_token: Option<String>,
+    request_style: RequestStyle,
+    token_placement: TokenPlacement,
+    timeout: Option<u64>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<u64>,
+    tcp_keepalive: Option<u64>,
+    user_agent: Option<String>,
+    rate_limit: Option<RateLimit>,
+    retry: Option<RetryPolicy>,
+    message_format: MessageFormat,
+}
+impl HttpApiBuilder {
+    pub fn access_token(mut self, token: &str) -> Self {
+        self.access_token = Some(token.to_string());
+        self
+    }
+    pub fn request_style(mut self, style: RequestStyle) -> Self {
+        self.request_style = style;
+        self
+    }
+    pub fn token_placement(mut self, placement: TokenPlacement) -> Self {
+        self.token_placement = placement;
+        self
+    }
+    /// Timeout of a whole action call, in seconds.
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Maximum number of idle connections kept open to the Onebot implementation.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+    /// How long an idle connection is kept in the pool, in seconds.
+    pub fn pool_idle_timeout(mut self, timeout: u64) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+    /// Interval of TCP keep-alive probes, in seconds.
+    pub fn tcp_keepalive(mut self, interval: u64) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+    /// Hold back outgoing messages that exceed `limit` instead of sending them at once.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+    /// Retry actions that fail on the way to the Onebot implementation.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+    /// Send messages as CQ code strings with [`MessageFormat::String`], arrays by default.
+    pub fn message_format(mut self, format: MessageFormat) -> Self {
+        self.message_format = format;
+        self
+    }
+    pub fn build(self) -> Result<HttpApi, Error> {
+        let mut client = reqwest::ClientBuilder::new();
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            client = client.pool_idle_timeout(Duration::from_secs(timeout));
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            client = client.tcp_keepalive(Duration::from_secs(interval));
+        }
+        if let Some(user_agent) = self.user_agent {
+            client = client.user_agent(user_agent);
+        }
+        Ok(HttpApi {
+            api_root: self.api_root,
+            access_token: self.access_token,
+            request_style: self.request_style,
+            token_placement: self.token_placement,
+            client: client.build()?,
+            limiter: self.rate_limit.map(|limit| Arc::new(RateLimiter::new(limit))),
+            retry: self.retry,
+            message_format: self.message_format,
+        })
+    }
+}
+#[async_trait]
+impl OneBotApi for HttpApi {
+    async fn call_action(&self, action: &str, mut params: Value) -> Result<ActionResponse, Error> {
+        self.message_format.apply(&mut params);
+        if let (Some(limiter), Some(target)) = (&self.limiter, Target::of_action(action, &params)) {
+            limiter.acquire(target).await?;
+        }
+        let Some(retry) = self.retry.as_ref().filter(|retry| retry.applies_to(action)) else {
+            return self.call_action_once(action, &params).await;
+        };
+        let mut attempt = 1;
+        loop {
+            let res = self.call_action_once(action, &params).await;
+            let (retcode, error) = match &res {
+                Ok(res) => (Some(res.retcode), None),
+                Err(e) => (None, Some(e)),
+            };
+            if retcode == Some(0) || !retry.should_retry(attempt, retcode, error) {
+                return res;
+            }
+            tokio::time::sleep(retry.backoff_after(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum MsgType{
     Private,
     Group,
     Discuss,
 }
-pub struct WebSocketReverseApi{
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{message::MessageSegment, retry::RetryOn};
+    #[test]
+    fn test_into_typed() {
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"ok","retcode":0,"data":{"message_id":42}}"#).unwrap();
+        let res: ActionResponse<MessageId> = res.into_typed().unwrap();
+        assert_eq!(res.data.message_id, 42);
+        for data in ["{}", "null"] {
+            let res: ActionResponse = serde_json::from_str(&format!(r#"{{"status":"ok","retcode":0,"data":{}}}"#, data)).unwrap();
+            assert!(res.into_typed::<Empty>().is_ok());
+        }
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"failed","retcode":1404,"data":null}"#).unwrap();
+        assert!(matches!(res.into_typed::<Empty>(), Err(Error::ActionNotFound)));
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"async","retcode":1,"data":null}"#).unwrap();
+        assert!(matches!(res.into_typed::<Empty>(), Err(Error::Async)));
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"failed","retcode":100,"data":null}"#).unwrap();
+        assert!(matches!(res.into_typed::<Empty>(), Err(Error::ActionFailed(100))));
+    }
+    #[tokio::test]
+    async fn test_with_mode() {
+        struct Queue;
+        #[async_trait]
+        impl OneBotApi for Queue {
+            async fn call_action(&self, action: &str, _params: Value) -> Result<ActionResponse, Error> {
+                let res = serde_json::json!({"status":"async","retcode":1,"data":null,"echo":action});
+                Ok(serde_json::from_value(res).unwrap())
+            }
+        }
+        let res = Queue.with_mode(CallMode::Async).send_group_msg(1, "hi", false, 2).await.unwrap();
+        assert_eq!(res.echo.as_ref().unwrap(), "send_group_msg_async");
+        assert_eq!(res.data.message_id, 0);
+        assert!(res.is_queued());
+        assert!(matches!(Queue.get_msg(1, 2).await, Err(Error::Async)));
+        let res = Queue.with_mode(CallMode::Async).send_msg(MsgType::Discuss, 3, 4, "hi", false, 2).await.unwrap();
+        assert_eq!(res.echo.unwrap(), "send_msg_async");
+    }
+    #[tokio::test]
+    async fn test_call_actions_with_mode() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = HttpApi::builder(&format!("http://{}", listener.local_addr().unwrap())).build().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let body = r#"{"status":"async","retcode":1,"data":null}"#;
+            let res = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+            stream.write_all(res.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+        api.call_actions_with_mode("send_group_msg", CallMode::RateLimited, r#"{"group_id":1,"message":"hi"}"#).await.unwrap();
+        let req = server.await.unwrap();
+        assert!(req.starts_with("POST /send_group_msg_rate_limited "));
+    }
+    #[tokio::test]
+    async fn test_get_query_style() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = HttpApi::builder(&format!("http://{}", listener.local_addr().unwrap()))
+            .access_token("token")
+            .request_style(RequestStyle::GetQuery)
+            .token_placement(TokenPlacement::Query)
+            .build()
+            .unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let body = r#"{"status":"ok","retcode":0,"data":null}"#;
+            let res = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+            stream.write_all(res.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+        api.set_group_whole_ban(1, true, 2).await.unwrap();
+        let req = server.await.unwrap();
+        let line = req.lines().next().unwrap();
+        assert!(line.starts_with("GET /set_group_whole_ban?access_token=token&"));
+        assert!(line.contains("group_id=1") && line.contains("enable=true"));
+        assert!(!req.to_lowercase().contains("authorization"));
+    }
+    #[tokio::test]
+    async fn test_save_image() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        struct Local(String);
+        #[async_trait]
+        impl OneBotApi for Local {
+            async fn call_action(&self, action: &str, _params: Value) -> Result<ActionResponse, Error> {
+                assert_eq!(action, "get_image");
+                let res = serde_json::json!({"status":"ok","retcode":0,"data":{"file":self.0}});
+                Ok(serde_json::from_value(res)?)
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("iocqhttp-save-image-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let cached = dir.join("cached.png");
+        tokio::fs::write(&cached, b"cached").await.unwrap();
+        let api = Local(cached.to_string_lossy().to_string());
+        let client = reqwest::Client::new();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/a.png", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\ndownloaded").await.unwrap();
+        });
+        let mut data = HashMap::new();
+        data.insert("url".to_string(), url);
+        let path = dir.join("url.png");
+        api.save_image(&client, &MessageSegment::new("image", Some(data)), &path, 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"downloaded");
+
+        let segment = MessageSegment::image("abc.image", None, None, None, None);
+        let path = dir.join("file.png");
+        api.save_image(&client, &segment, &path, 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"cached");
+
+        let err = api.save_image(&client, &MessageSegment::new("image", None), &path, 1).await;
+        assert!(matches!(err, Err(Error::MissingData("file"))));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_retry_server_error() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let root = format!("http://{}", listener.local_addr().unwrap());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let res = if counter.fetch_add(1, Ordering::SeqCst).is_multiple_of(2) {
+                    let body = "<html>502 Bad Gateway</html>";
+                    format!("HTTP/1.1 502 Bad Gateway\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}", body.len(), body)
+                } else {
+                    let body = r#"{"status":"ok","retcode":0,"data":{"user_id":1,"nickname":"a"}}"#;
+                    format!("HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}", body.len(), body)
+                };
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let api = HttpApi::builder(&root).retry(policy.clone()).build().unwrap();
+        let res = api.get_login_info(1).await.unwrap();
+        assert_eq!(res.data.user_id, 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
 
+        let api = HttpApi::builder(&root).retry(policy.retry_on(&[RetryOn::Timeout])).build().unwrap();
+        assert!(matches!(api.get_login_info(1).await, Err(Error::ServerError(502))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+    #[test]
+    fn test_get_msg_result() {
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"ok","retcode":0,"data":{
+            "time":1,"message_type":"group","message_id":2,"real_id":3,
+            "sender":{"nickname":"a","user_id":10},
+            "message":[{"type":"text","data":{"text":"hi"}},{"type":"face","data":{"id":"1"}}]}}"#).unwrap();
+        let res: ActionResponse<GetMsgResult> = res.into_typed().unwrap();
+        assert_eq!(res.data.sender.user_id, 10);
+        assert_eq!(res.data.message.segments()[1], MessageSegment::face(&1));
+    }
+    #[test]
+    fn test_forward_msg() {
+        let content = r#"[{"type":"text","data":{"text":"hi"}}]"#;
+        let onebot = format!(r#"{{"message":[{{"type":"node","data":{{"user_id":"10","nickname":"a","content":{}}}}}]}}"#, content);
+        let nodes: Vec<ForwardNode> = serde_json::from_str::<ForwardMsg>(&onebot).unwrap().nodes;
+        assert_eq!(nodes[0].user_id, 10);
+        assert_eq!(nodes[0].content.segments()[0], MessageSegment::text("hi"));
+        let gocq = format!(r#"{{"messages":[{{"sender":{{"nickname":"b","user_id":11}},"time":5,"content":{}}}]}}"#, content);
+        let nodes: Vec<ForwardNode> = serde_json::from_str::<ForwardMsg>(&gocq).unwrap().nodes;
+        assert_eq!(nodes[0].nickname, "b");
+        assert_eq!(nodes[0].time, Some(5));
+    }
+    #[test]
+    fn test_status() {
+        let status: BotStatus = serde_json::from_str(r#"{"online":null,"good":true,"app_initialized":true,"stat":{"message_sent":3}}"#).unwrap();
+        assert_eq!(status.online, None);
+        assert_eq!(status.stat.message_sent, 3);
+        assert_eq!(status.extra["app_initialized"], true);
+        let version: VersionInfo = serde_json::from_str(r#"{"app_name":"go-cqhttp","protocol_version":"v11","runtime_os":"linux"}"#).unwrap();
+        assert_eq!(version.protocol_version, "v11");
+        assert_eq!(version.extra["runtime_os"], "linux");
+    }
+    #[test]
+    fn test_credentials_redacted() {
+        let credentials: Credentials = serde_json::from_str(r#"{"cookies":"uin=o123; skey=secret","csrf_token":987654}"#).unwrap();
+        let debug = format!("{:?}", credentials);
+        assert!(!debug.contains("secret"));
+        assert!(!debug.contains("987654"));
+    }
+    #[test]
+    fn test_group_member_info() {
+        let res: ActionResponse = serde_json::from_str(r#"{"status":"ok","retcode":0,"data":[{
+            "group_id":1,"user_id":2,"nickname":"a","card":"b","role":"admin","level":"5","title":"t"}]}"#).unwrap();
+        let res: ActionResponse<Vec<GroupMemberInfo>> = res.into_typed().unwrap();
+        assert_eq!(res.data[0].role, Role::Admin);
+        assert_eq!(res.data[0].card, "b");
+        assert!(!res.data[0].unfriendly);
+    }
 }
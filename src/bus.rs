@@ -1,11 +1,14 @@
-use std::{collections::HashMap};
+use std::{collections::HashMap, sync::Arc};
+use crate::event::Event;
 pub trait EventHandler {
     type Output;
     type Args;
     fn handle_event(&self, args: Self::Args) -> Self::Output;
 }
-type AsyncFunc<T> = Box<dyn EventHandler<Output = (), Args = T>>;
+type AsyncFunc<T> = Box<dyn EventHandler<Output = (), Args = T> + Send + Sync>;
 type AsyncFuncMap<T> = HashMap<String, AsyncFunc<T>>;
+/// Hands a parsed event from a transport to the bus it was built for.
+pub type EventDispatcher = Arc<dyn Fn(Event) + Send + Sync>;
 #[derive(Default)]
 pub struct EventBus<T> {
     subscribers: AsyncFuncMap<T>,
@@ -37,4 +40,39 @@ impl<T> EventBus<T> {
             handler.handle_event(args);
         }
     }
+    /// Pass `args` to every hook, then to every subscriber.
+    pub fn broadcast(&self, args: T)
+    where
+        T: Clone,
+    {
+        self.hooks_before
+            .values()
+            .chain(self.subscribers.values())
+            .for_each(|handler| handler.handle_event(args.clone()));
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    struct Recorder(Arc<Mutex<Vec<String>>>, &'static str);
+    impl EventHandler for Recorder {
+        type Output = ();
+        type Args = i32;
+        fn handle_event(&self, args: i32) {
+            self.0.lock().unwrap().push(format!("{}:{}", self.1, args));
+        }
+    }
+    #[test]
+    fn test_broadcast() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = EventBus::new();
+        bus.subscribe("a", Box::new(Recorder(log.clone(), "a")));
+        bus.subscribe("b", Box::new(Recorder(log.clone(), "b")));
+        bus.hook_before("h", Box::new(Recorder(log.clone(), "h")));
+        bus.broadcast(1);
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0], "h:1");
+    }
 }
@@ -2,6 +2,7 @@ use thiserror::Error;
 use reqwest::Error as ReqwestError;
 use serde_json::Error as SerdeError;
 use std::io::Error as IoError;
+use tokio_tungstenite::tungstenite::Error as WebSocketError;
 
 
 #[derive(Error, Debug)]
@@ -12,8 +13,49 @@ pub enum Error {
     Serde(#[from] SerdeError),
     #[error("IO Error: {0}")]
     Io(#[from] IoError),
-    #[error("Onebot API Error: {0}")]
-    OnebotApi(String),
+    #[error("Onebot API Error: action is being handled asynchronously (retcode 1)")]
+    Async,
+    #[error("Onebot API Error: bad request (retcode 1400)")]
+    BadRequest,
+    #[error("Onebot API Error: access token missing (retcode 1401)")]
+    Unauthorized,
+    #[error("Onebot API Error: access token rejected (retcode 1403)")]
+    Forbidden,
+    #[error("Onebot API Error: action not found (retcode 1404)")]
+    ActionNotFound,
+    #[error("Onebot API Error: action failed with retcode {0}")]
+    ActionFailed(i64),
+    #[error("WebSocket Error: {0}")]
+    WebSocket(Box<WebSocketError>),
+    #[error("No Onebot connection available")]
+    NotConnected,
+    #[error("HTTP Error: server replied with status {0}")]
+    ServerError(u16),
+    #[error("Onebot API call timed out")]
+    Timeout,
+    #[error("Send queue is full, message dropped")]
+    RateLimited,
+    #[error("Message segment is not of type {0}")]
+    SegmentType(&'static str),
+    #[error("Message segment has no {0}")]
+    MissingData(&'static str),
     #[error("Event to reply is not correct")]
     ReplyEvent,
-}
\ No newline at end of file
+    #[error("CQ code Error: {0}")]
+    Parse(#[from] ParseError),
+}
+/// Error parsing a CQ code string, holding the byte offset of the code at fault.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("code at {0} is not closed")]
+    Unclosed(usize),
+    #[error("code at {0} has no type")]
+    MissingType(usize),
+    #[error("code at {0} has a parameter without a value")]
+    MissingValue(usize),
+}
+impl From<WebSocketError> for Error {
+    fn from(e: WebSocketError) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
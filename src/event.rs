@@ -1,3 +1,9 @@
+use serde::{Deserialize, Serialize};
+use crate::{
+    api::{ActionResponse, Empty, OneBotApi},
+    error::Error,
+    message::Message,
+};
 macro_rules! make_event{
     (
      $(#[$meta:meta])*
@@ -39,7 +45,7 @@ macro_rules! make_msg_event {
                 sub_type:String,
                 message_id:i64,
                 user_id:i64,
-                message:String,
+                message:Message,
                 raw_message:String,
                 font:i32,
                 sender:Sender,
@@ -75,12 +81,34 @@ macro_rules! make_notice_event {
 
        }
 }
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Admin,
+    #[default]
+    Member,
+}
+/// The sender of a message.
+/// `card`, `area`, `level`, `role` and `title` are only present in group messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Sender {
+    #[serde(default)]
     pub age: i32,
     pub nickname: String,
+    #[serde(default)]
     pub sex: String,
     pub user_id: i64,
+    #[serde(default)]
+    pub card: Option<String>,
+    #[serde(default)]
+    pub area: Option<String>,
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub title: Option<String>,
 }
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct FileInfo {
@@ -97,9 +125,9 @@ pub struct OfflineFile {
 }
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Anonymous {
-    id: i64,
-    name: String,
-    flag: String,
+    pub id: i64,
+    pub name: String,
+    pub flag: String,
 }
 make_event! {
     struct PrivateMessage{
@@ -107,7 +135,7 @@ make_event! {
         sub_type:String,
         message_id:i64,
         user_id:i64,
-        message:String,
+        message:Message,
         raw_message:String,
         font:i32,
         sender:Sender,
@@ -119,6 +147,18 @@ make_msg_event! {
         anonymous:Option<Anonymous>,
     }
 }
+make_event! {
+    struct DiscussMessage{
+        message_type:String,
+        message_id:i64,
+        user_id:i64,
+        discuss_id:i64,
+        message:Message,
+        raw_message:String,
+        font:i32,
+        sender:Sender,
+    }
+}
 make_notice_event! {
     struct GroupFileUpload{
         group_id:i64,
@@ -219,17 +259,37 @@ make_event! {
         flag:String,
     }
 }
+impl FriendRequest {
+    /// Accept the request, `remark` becomes the new friend's remark.
+    pub async fn approve<A: OneBotApi + ?Sized>(&self, api: &A, remark: &str) -> Result<ActionResponse<Empty>, Error> {
+        api.set_friend_add_request(&self.flag, true, remark, self.self_id).await
+    }
+    pub async fn reject<A: OneBotApi + ?Sized>(&self, api: &A) -> Result<ActionResponse<Empty>, Error> {
+        api.set_friend_add_request(&self.flag, false, "", self.self_id).await
+    }
+}
+impl GroupRequest {
+    pub async fn approve<A: OneBotApi + ?Sized>(&self, api: &A) -> Result<ActionResponse<Empty>, Error> {
+        api.set_group_add_request(&self.flag, &self.sub_type, true, "", self.self_id).await
+    }
+    /// Refuse the request or invitation, `reason` is shown to the requester.
+    pub async fn reject<A: OneBotApi + ?Sized>(&self, api: &A, reason: &str) -> Result<ActionResponse<Empty>, Error> {
+        api.set_group_add_request(&self.flag, &self.sub_type, false, reason, self.self_id).await
+    }
+}
 make_event! {
     struct MetaEvent{
         meta_event_type:String,
-        status:String,
-        interval:i64,
+        sub_type:Option<String>,
+        status:Option<serde_json::Value>,
+        interval:Option<i64>,
     }
 }
 #[derive(Debug)]
 pub enum Event {
     PrivateMessage(PrivateMessage),
     GroupMessage(GroupMessage),
+    DiscussMessage(DiscussMessage),
     GroupFileUpload(GroupFileUpload),
     GroupAdminChange(GroupAdminChange),
     GroupMemberReduce(GroupMemberReduce),
@@ -256,73 +316,104 @@ impl Event {
 pub enum MsgEvent {
     PrivateMessage(PrivateMessage),
     GroupMessage(GroupMessage),
+    DiscussMessage(DiscussMessage),
 }
 pub fn get_event(event: &serde_json::Value) -> Result<Event, serde_json::Error> {
-    let post_type = event["post_type"].as_str().unwrap();
+    let post_type = event["post_type"].as_str().unwrap_or_default();
     match post_type {
         "message" => {
-            let message_type = event["message_type"].as_str().unwrap();
+            let message_type = event["message_type"].as_str().unwrap_or_default();
             match message_type {
                 "private" => Ok(Event::PrivateMessage(serde_json::from_value(
                     event.clone(),
                 )?)),
                 "group" => Ok(Event::GroupMessage(serde_json::from_value(event.clone())?)),
+                "discuss" => Ok(Event::DiscussMessage(serde_json::from_value(event.clone())?)),
                 _ => Ok(Event::Unknown),
             }
         }
         "notice" => {
-            let notice_type = event["notice_type"].as_str().unwrap();
+            let notice_type = event["notice_type"].as_str().unwrap_or_default();
             match notice_type {
                 "group_upload" => Ok(Event::GroupFileUpload(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group_admin" => Ok(Event::GroupAdminChange(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group_decrease" => Ok(Event::GroupMemberReduce(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group_increase" => Ok(Event::GroupMemberIncrease(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group_ban" => Ok(Event::GroupMute(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "friend_add" => Ok(Event::FriendAdd(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group_recall" => Ok(Event::GroupMessageRecall(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "friend_recall" => Ok(Event::FriendMessageRecall(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "poke" => Ok(Event::GroupPoke(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "offline_file" => Ok(Event::OfflineFileUpload(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 _ => Ok(Event::Unknown),
             }
         }
 
         "request" => {
-            let request_type = event["request_type"].as_str().unwrap();
+            let request_type = event["request_type"].as_str().unwrap_or_default();
             match request_type {
                 "friend" => Ok(Event::FriendRequest(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 "group" => Ok(Event::GroupRequest(
-                    serde_json::from_value(event.clone()).unwrap(),
+                    serde_json::from_value(event.clone())?,
                 )),
                 _ => Ok(Event::Unknown),
             }
         }
         "meta_event" => Ok(Event::MetaEvent(
-            serde_json::from_value(event.clone()).unwrap(),
+            serde_json::from_value(event.clone())?,
         )),
 
         _ => Ok(Event::Unknown),
     }
 }
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_message_formats() {
+        let mut event = serde_json::json!({
+            "post_type":"message","message_type":"group","sub_type":"normal","self_id":1,"time":0,
+            "message_id":2,"user_id":3,"group_id":4,"anonymous":null,
+            "message":"hi[CQ:face,id=1]","raw_message":"hi[CQ:face,id=1]","font":0,
+            "sender":{"user_id":3,"nickname":"a"}
+        });
+        let Ok(Event::GroupMessage(from_str)) = get_event(&event) else { panic!("not a group message") };
+        event["message"] = serde_json::json!([{"type":"text","data":{"text":"hi"}},{"type":"face","data":{"id":"1"}}]);
+        let Ok(Event::GroupMessage(from_array)) = get_event(&event) else { panic!("not a group message") };
+        assert_eq!(from_str.message, from_array.message);
+        assert_eq!(from_array.raw_message, "hi[CQ:face,id=1]");
+    }
+    #[test]
+    fn test_meta_event() {
+        let lifecycle = r#"{"post_type":"meta_event","meta_event_type":"lifecycle","sub_type":"connect","self_id":1,"time":0}"#;
+        let Ok(Event::MetaEvent(e)) = Event::from_str(lifecycle) else { panic!("not a meta event") };
+        assert_eq!(e.sub_type.as_deref(), Some("connect"));
+        let heartbeat = r#"{"post_type":"meta_event","meta_event_type":"heartbeat","self_id":1,"time":0,"status":{"online":true,"good":true},"interval":5000}"#;
+        let Ok(Event::MetaEvent(e)) = Event::from_str(heartbeat) else { panic!("not a meta event") };
+        assert_eq!(e.interval, Some(5000));
+        let bad_notice = r#"{"post_type":"notice","notice_type":"group_ban","self_id":1,"time":0}"#;
+        assert!(Event::from_str(bad_notice).is_err());
+    }
+}
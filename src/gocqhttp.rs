@@ -0,0 +1,276 @@
+//! Actions only provided by go-cqhttp, enabled by the `gocqhttp` feature.
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::{
+    action,
+    api::{ActionResponse, Empty, OneBotApi},
+    error::Error,
+    event::GroupMessage,
+    message::MessageSegment,
+};
+/// `data` of `send_group_forward_msg` and `send_private_forward_msg`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ForwardMsgId {
+    pub message_id: i64,
+    #[serde(default)]
+    pub forward_id: Option<String>,
+}
+/// A file in a group's file system.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GroupFile {
+    pub group_id: i64,
+    pub file_id: String,
+    pub file_name: String,
+    pub busid: i32,
+    pub file_size: i64,
+    #[serde(default)]
+    pub upload_time: i64,
+    #[serde(default)]
+    pub dead_time: i64,
+    #[serde(default)]
+    pub modify_time: i64,
+    #[serde(default)]
+    pub download_times: i32,
+    #[serde(default)]
+    pub uploader: i64,
+    #[serde(default)]
+    pub uploader_name: String,
+}
+/// A folder in a group's file system.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GroupFolder {
+    pub group_id: i64,
+    pub folder_id: String,
+    pub folder_name: String,
+    #[serde(default)]
+    pub create_time: i64,
+    #[serde(default)]
+    pub creator: i64,
+    #[serde(default)]
+    pub creator_name: String,
+    #[serde(default)]
+    pub total_file_count: i32,
+}
+/// `data` of `get_group_root_files` and `get_group_files_by_folder`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GroupFiles {
+    /// go-cqhttp sends `null` for an empty list.
+    #[serde(default, deserialize_with = "de_null_as_empty")]
+    pub files: Vec<GroupFile>,
+    #[serde(default, deserialize_with = "de_null_as_empty")]
+    pub folders: Vec<GroupFolder>,
+}
+/// `data` of `get_group_file_url`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FileUrl {
+    pub url: String,
+}
+/// `data` of `get_group_msg_history`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MsgHistory {
+    pub messages: Vec<GroupMessage>,
+}
+fn de_null_as_empty<'de, D, T>(de: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(de)?.unwrap_or_default())
+}
+make_action! {
+    /// Parameters of `send_group_forward_msg`.
+    SendGroupForwardMsgParams => "send_group_forward_msg" -> ForwardMsgId {
+        group_id:i64,
+        messages:Vec<MessageSegment>,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `send_private_forward_msg`.
+    SendPrivateForwardMsgParams => "send_private_forward_msg" -> ForwardMsgId {
+        user_id:i64,
+        messages:Vec<MessageSegment>,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `upload_group_file`.
+    UploadGroupFileParams => "upload_group_file" -> Empty {
+        group_id:i64,
+        file:String,
+        name:String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        folder:Option<String>,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_root_files`.
+    GetGroupRootFilesParams => "get_group_root_files" -> GroupFiles {
+        group_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_files_by_folder`.
+    GetGroupFilesByFolderParams => "get_group_files_by_folder" -> GroupFiles {
+        group_id:i64,
+        folder_id:String,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_file_url`.
+    GetGroupFileUrlParams => "get_group_file_url" -> FileUrl {
+        group_id:i64,
+        file_id:String,
+        busid:i32,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `set_essence_msg`.
+    SetEssenceMsgParams => "set_essence_msg" -> Empty {
+        message_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `delete_essence_msg`.
+    DeleteEssenceMsgParams => "delete_essence_msg" -> Empty {
+        message_id:i64,
+        self_id:i64,
+    }
+}
+make_action! {
+    /// Parameters of `get_group_msg_history`.
+    GetGroupMsgHistoryParams => "get_group_msg_history" -> MsgHistory {
+        group_id:i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message_seq:Option<i64>,
+        self_id:i64,
+    }
+}
+/// go-cqhttp extension actions, available on every [`OneBotApi`].
+#[async_trait]
+pub trait GoCqhttpApi: OneBotApi {
+    ///
+    /// 发送合并转发(群聊)\
+    /// `group_id`:群号\
+    /// `messages`:自定义转发消息，见 [`MessageSegment::node`] 和 [`MessageSegment::custom_node`]\
+    /// `self_id`:机器人QQ
+    async fn send_group_forward_msg(&self,group_id:i64,messages:&[MessageSegment],self_id:i64)->Result<ActionResponse<ForwardMsgId>,Error>{
+        action::call(self,&SendGroupForwardMsgParams{
+            group_id,
+            messages:messages.to_vec(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 发送合并转发(好友)\
+    /// `user_id`:好友QQ号\
+    /// `messages`:自定义转发消息，见 [`MessageSegment::node`] 和 [`MessageSegment::custom_node`]\
+    /// `self_id`:机器人QQ
+    async fn send_private_forward_msg(&self,user_id:i64,messages:&[MessageSegment],self_id:i64)->Result<ActionResponse<ForwardMsgId>,Error>{
+        action::call(self,&SendPrivateForwardMsgParams{
+            user_id,
+            messages:messages.to_vec(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 上传群文件\
+    /// `group_id`:群号\
+    /// `file`:go-cqhttp 所在主机上的本地文件路径\
+    /// `name`:储存名称\
+    /// `folder`:父目录ID，`None` 表示上传到根目录\
+    /// `self_id`:机器人QQ
+    async fn upload_group_file(&self,group_id:i64,file:&str,name:&str,folder:Option<&str>,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&UploadGroupFileParams{
+            group_id,
+            file:file.to_string(),
+            name:name.to_string(),
+            folder:folder.map(str::to_string),
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群根目录文件列表\
+    /// `group_id`:群号\
+    /// `self_id`:机器人QQ
+    async fn get_group_root_files(&self,group_id:i64,self_id:i64)->Result<ActionResponse<GroupFiles>,Error>{
+        action::call(self,&GetGroupRootFilesParams{
+            group_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群子目录文件列表\
+    /// `group_id`:群号\
+    /// `folder_id`:文件夹ID\
+    /// `self_id`:机器人QQ
+    async fn get_group_files_by_folder(&self,group_id:i64,folder_id:&str,self_id:i64)->Result<ActionResponse<GroupFiles>,Error>{
+        action::call(self,&GetGroupFilesByFolderParams{
+            group_id,
+            folder_id:folder_id.to_string(),
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群文件资源链接\
+    /// `group_id`:群号\
+    /// `file_id`:文件ID\
+    /// `busid`:文件类型\
+    /// `self_id`:机器人QQ
+    async fn get_group_file_url(&self,group_id:i64,file_id:&str,busid:i32,self_id:i64)->Result<ActionResponse<FileUrl>,Error>{
+        action::call(self,&GetGroupFileUrlParams{
+            group_id,
+            file_id:file_id.to_string(),
+            busid,
+            self_id,
+        }).await
+    }
+    ///
+    /// 设置精华消息\
+    /// `message_id`:消息ID\
+    /// `self_id`:机器人QQ
+    async fn set_essence_msg(&self,message_id:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&SetEssenceMsgParams{
+            message_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 移出精华消息\
+    /// `message_id`:消息ID\
+    /// `self_id`:机器人QQ
+    async fn delete_essence_msg(&self,message_id:i64,self_id:i64)->Result<ActionResponse<Empty>,Error>{
+        action::call(self,&DeleteEssenceMsgParams{
+            message_id,
+            self_id,
+        }).await
+    }
+    ///
+    /// 获取群消息历史记录\
+    /// `group_id`:群号\
+    /// `message_seq`:起始消息序号，`None` 表示从最新消息开始\
+    /// `self_id`:机器人QQ
+    async fn get_group_msg_history(&self,group_id:i64,message_seq:Option<i64>,self_id:i64)->Result<ActionResponse<MsgHistory>,Error>{
+        action::call(self,&GetGroupMsgHistoryParams{
+            group_id,
+            message_seq,
+            self_id,
+        }).await
+    }
+}
+impl<A: OneBotApi + ?Sized> GoCqhttpApi for A {}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_group_files() {
+        let files: GroupFiles = serde_json::from_str(r#"{"files":null,"folders":[{"group_id":1,"folder_id":"/a","folder_name":"a"}]}"#).unwrap();
+        assert!(files.files.is_empty());
+        assert_eq!(files.folders[0].folder_id, "/a");
+    }
+}
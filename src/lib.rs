@@ -1,32 +1,59 @@
 #![allow(clippy::let_unit_value)]
 
-use api::HttpApi;
-use error::Error;
+#[macro_use]
+pub mod action;
 pub mod api;
 pub mod bus;
 pub mod error;
 pub mod event;
+#[cfg(feature = "gocqhttp")]
+pub mod gocqhttp;
+pub mod limit;
 pub mod message;
+pub mod retry;
+pub mod ws;
+pub use action::*;
 pub use api::*;
 pub use bus::*;
 pub use event::*;
+#[cfg(feature = "gocqhttp")]
+pub use gocqhttp::*;
 pub use error::*;
+pub use limit::{Budget, DropPolicy, RateLimit};
 pub use message::*;
-use event::Event;
+pub use retry::{RetryOn, RetryPolicy};
+pub use ws::*;
+use hmac::{Hmac, Mac};
 use log::{log, Level};
-use reqwest::Response;
-use rocket::{routes, Config};
-#[derive(Default)]
+use rocket::{
+    http::Status,
+    request::{self, FromRequest, Request},
+    routes, Config, State,
+};
+use sha1::Sha1;
+use std::sync::{Arc, RwLock};
 pub struct CQHttp<T: Default> {
-    api: HttpApi,
+    api: Arc<dyn OneBotApi>,
     //sever_app:Option<Rocket<Build>>,
     access_token: Option<String>,
     secret: Option<String>,
     api_root: String,
     api_timeout: Option<u64>,
-    bus: bus::EventBus<T>,
+    bus: Arc<RwLock<bus::EventBus<T>>>,
+}
+type AsyncFunc<T> = Box<dyn bus::EventHandler<Output = (), Args = T> + Send + Sync>;
+impl<T: Default> Default for CQHttp<T> {
+    fn default() -> Self {
+        Self {
+            api: Arc::new(HttpApi::default()),
+            access_token: None,
+            secret: None,
+            api_root: String::new(),
+            api_timeout: None,
+            bus: Arc::default(),
+        }
+    }
 }
-type AsyncFunc<T> = Box<dyn bus::EventHandler<Output = (), Args = T>>;
 #[allow(clippy::new_without_default)]
 impl<T> CQHttp<T> 
 where
@@ -38,21 +65,45 @@ where
             ..Default::default()
         }
     }
-    pub fn run_server(&mut self, ip: std::net::IpAddr, port: u16) -> rocket::Rocket<rocket::Build> {
-        let config = if let Some(key) = self.secret.clone() {
-            Config::figment()
-                .merge(("address", ip.to_string()))
-                .merge(("port", port))
-                .merge(("secret_key", key))
-        } else {
-            Config::figment()
-                .merge(("address", ip.to_string()))
-                .merge(("port", port))
-        };
+    pub fn run_server(&mut self, ip: std::net::IpAddr, port: u16) -> rocket::Rocket<rocket::Build>
+    where
+        T: From<Event> + Clone + Send + Sync + 'static,
+    {
+        let config = Config::figment()
+            .merge(("address", ip.to_string()))
+            .merge(("port", port));
         let config = Config::from(config);
         rocket::custom(config)
             .mount("/", routes![handle_event])
             .manage(self.secret.clone())
+            .manage(self.dispatcher())
+    }
+    /// Start a reverse websocket server that feeds its events into this bot's subscribers.
+    /// Spawn [`WebSocketReverseApi::serve`] on the returned value and keep a clone to call actions.
+    pub fn reverse_ws(&self) -> WebSocketReverseApi
+    where
+        T: From<Event> + Clone + Send + Sync + 'static,
+    {
+        WebSocketReverseApi::new(self.access_token.clone(), self.api_timeout)
+            .dispatcher(self.dispatcher())
+    }
+    /// Dial a forward websocket at `url` that feeds its events into this bot's subscribers.
+    /// Spawn [`WebSocketForwardApi::run`] on the returned value and keep a clone to call actions.
+    pub fn forward_ws(&self, url: &str) -> WebSocketForwardApi
+    where
+        T: From<Event> + Clone + Send + Sync + 'static,
+    {
+        WebSocketForwardApi::new(url, self.access_token.clone(), self.api_timeout)
+            .dispatcher(self.dispatcher())
+    }
+    fn dispatcher(&self) -> EventDispatcher
+    where
+        T: From<Event> + Clone + Send + Sync + 'static,
+    {
+        let bus = self.bus.clone();
+        Arc::new(move |event| {
+            bus.read().unwrap().broadcast(T::from(event));
+        })
     }
     pub fn access_token(mut self, token: &str) -> Self {
         self.access_token = Some(token.to_string());
@@ -67,9 +118,17 @@ where
         self
     }
     pub fn build_api(&mut self) {
-        self.api = HttpApi::new(&self.api_root, self.access_token.clone(), self.api_timeout);
+        self.api = Arc::new(HttpApi::new(&self.api_root, self.access_token.clone(), self.api_timeout));
+    }
+    /// Send actions over `api` instead of the HTTP api built by [`CQHttp::build_api`].
+    pub fn set_api<A: OneBotApi + 'static>(&mut self, api: A) {
+        self.api = Arc::new(api);
     }
-    pub async fn send<U: ToString>(&self, event: &Event, message: U) -> Result<Response, Error> {
+    pub fn api(&self) -> Arc<dyn OneBotApi> {
+        self.api.clone()
+    }
+    /// Reply to a message event. A `&str` is sent as plain text, parse CQ codes with [`Message::from_cq_str`].
+    pub async fn send<U: Into<Message> + Send>(&self, event: &Event, message: U) -> Result<ActionResponse<MessageId>, Error> {
         match event {
             Event::GroupMessage(e) => {
                 self.api
@@ -81,37 +140,81 @@ where
                     .send_private_msg(e.sender.user_id, message, false, e.self_id)
                     .await
             }
+            Event::DiscussMessage(e) => {
+                self.api
+                    .send_discuss_msg(e.discuss_id, message, false, e.self_id)
+                    .await
+            }
             _ => {
                 Err(Error::ReplyEvent)
             }
         }
     }
     pub fn subscribe(&mut self,name:&str,handler:AsyncFunc<T>){
-        self.bus.subscribe(name,handler);
+        self.bus.write().unwrap().subscribe(name,handler);
     }
     pub fn unsubscribe(&mut self,name:&str){
-        self.bus.unsubscribe(name);
+        self.bus.write().unwrap().unsubscribe(name);
     }
     pub fn hook_before(&mut self,name:&str,handler:AsyncFunc<T>){
-        self.bus.hook_before(name,handler);
+        self.bus.write().unwrap().hook_before(name,handler);
     }
     pub fn unhook_before(&mut self,name:&str){
-        self.bus.unhook_before(name);
+        self.bus.write().unwrap().unhook_before(name);
     }
 }
 
+/// The `X-Signature` header sent by the Onebot implementation, if any.
+struct Signature(Option<String>);
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Signature {
+    type Error = ();
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let signature = req.headers().get_one("X-Signature").map(|s| s.to_string());
+        request::Outcome::Success(Signature(signature))
+    }
+}
+/// Check `signature` (`sha1=<hex>`) against the HMAC-SHA1 of `body` keyed by `secret`.
+/// The digest comparison is constant-time.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(digest) = signature.strip_prefix("sha1=") else {
+        return false;
+    };
+    let Ok(digest) = hex::decode(digest) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&digest).is_ok()
+}
 #[rocket::post("/", data = "<data>")]
-fn handle_event(data: String) {
+fn handle_event(
+    data: String,
+    signature: Signature,
+    secret: &State<Option<String>>,
+    dispatcher: &State<EventDispatcher>,
+) -> Status {
+    if let Some(secret) = secret.inner() {
+        let verified = signature
+            .0
+            .map(|sig| verify_signature(secret, data.as_bytes(), &sig))
+            .unwrap_or(false);
+        if !verified {
+            log!(Level::Warn, "rejected event with invalid signature");
+            return Status::Unauthorized;
+        }
+    }
     let event = Event::from_str(&data);
     if let Err(e) = event {
         log!(Level::Error, "{}", e);
-        return;
+        return Status::BadRequest;
     }
     let event = event.unwrap();
     log_event(&event);
-    todo!()
+    dispatcher(event);
+    Status::NoContent
 }
-fn log_event(event: &Event) {
+pub(crate) fn log_event(event: &Event) {
     match event {
         Event::GroupMessage(e) => {
             let group_id = e.group_id;
@@ -131,6 +234,59 @@ fn log_event(event: &Event) {
             let info = format!("收到私聊消息{}({}):{}", sender_id, sender_name, message);
             log!(Level::Info, "{}", info);
         }
+        Event::DiscussMessage(e) => {
+            let discuss_id = e.discuss_id;
+            let sender_id = e.sender.user_id;
+            let sender_name = e.sender.nickname.clone();
+            let message = e.message.clone();
+            let info = format!(
+                "收到讨论组{}的消息{}({}):{}",
+                discuss_id, sender_id, sender_name, message
+            );
+            log!(Level::Info, "{}", info);
+        }
         _ => {}
     }
 }
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"post_type":"meta_event"}"#;
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let signature = format!("sha1={}", hex::encode(mac.finalize().into_bytes()));
+        assert!(verify_signature("secret", body, &signature));
+        assert!(!verify_signature("other", body, &signature));
+        assert!(!verify_signature("secret", body, "sha1=zz"));
+        assert!(!verify_signature("secret", body, &signature[5..]));
+    }
+    #[rocket::async_test]
+    async fn test_handle_event_signature() {
+        use rocket::{http::Header, local::asynchronous::Client};
+        let dispatcher: EventDispatcher = Arc::new(|_| {});
+        let rocket = rocket::build()
+            .mount("/", routes![handle_event])
+            .manage(Some("secret".to_string()))
+            .manage(dispatcher);
+        let client = Client::tracked(rocket).await.unwrap();
+        let body = r#"{"post_type":"meta_event","meta_event_type":"lifecycle","sub_type":"connect","self_id":1,"time":0}"#;
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"secret").unwrap();
+        mac.update(body.as_bytes());
+        let signature = format!("sha1={}", hex::encode(mac.finalize().into_bytes()));
+
+        let res = client.post("/").body(body).dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        let res = client.post("/").body(body).header(Header::new("X-Signature", "sha1=00")).dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        let res = client.post("/").body(body).header(Header::new("X-Signature", signature)).dispatch().await;
+        assert_eq!(res.status(), Status::NoContent);
+        let body = r#"{"post_type":"notice","notice_type":"group_ban","self_id":1,"time":0}"#;
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"secret").unwrap();
+        mac.update(body.as_bytes());
+        let signature = format!("sha1={}", hex::encode(mac.finalize().into_bytes()));
+        let res = client.post("/").body(body).header(Header::new("X-Signature", signature)).dispatch().await;
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+}
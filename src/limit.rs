@@ -0,0 +1,328 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::{
+    sync::{oneshot, Notify},
+    time::Instant,
+};
+
+use crate::error::Error;
+
+/// Refill `per_second` tokens a second, holding at most `burst` of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub burst: u32,
+    pub per_second: f64,
+}
+/// What to do with a send when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropPolicy {
+    /// Fail the new send.
+    #[default]
+    DropNewest,
+    /// Fail the send that has waited longest and queue the new one.
+    DropOldest,
+}
+/// Client-side limits on outgoing messages, see [`crate::HttpApiBuilder::rate_limit`].
+///
+/// A send waits until the global budget and the budget of its group (or of its user, for
+/// private messages) both have a token. At most `max_queue` sends wait at a time.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    global: Option<Budget>,
+    per_group: Option<Budget>,
+    per_user: Option<Budget>,
+    max_queue: usize,
+    drop_policy: DropPolicy,
+}
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            global: None,
+            per_group: None,
+            per_user: None,
+            max_queue: 100,
+            drop_policy: DropPolicy::default(),
+        }
+    }
+}
+impl RateLimit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn global(mut self, burst: u32, per_second: f64) -> Self {
+        self.global = Some(Budget { burst, per_second });
+        self
+    }
+    pub fn per_group(mut self, burst: u32, per_second: f64) -> Self {
+        self.per_group = Some(Budget { burst, per_second });
+        self
+    }
+    pub fn per_user(mut self, burst: u32, per_second: f64) -> Self {
+        self.per_user = Some(Budget { burst, per_second });
+        self
+    }
+    pub fn max_queue(mut self, max_queue: usize) -> Self {
+        self.max_queue = max_queue;
+        self
+    }
+    pub fn drop_policy(mut self, drop_policy: DropPolicy) -> Self {
+        self.drop_policy = drop_policy;
+        self
+    }
+}
+
+/// Buckets that are full are forgotten once there are more than this many.
+const PRUNE_ABOVE: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+impl Bucket {
+    fn new(budget: &Budget, now: Instant) -> Self {
+        Bucket {
+            tokens: budget.burst as f64,
+            last: now,
+        }
+    }
+    fn refill(&mut self, budget: &Budget, now: Instant) {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * budget.per_second).min(budget.burst as f64);
+        self.last = now;
+    }
+    /// Time until a token is available.
+    fn wait(&self, budget: &Budget) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if budget.per_second <= 0.0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / budget.per_second)
+        }
+    }
+}
+/// Who a message is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Group(i64),
+    User(i64),
+}
+impl Target {
+    /// The target of `action` if it sends a message, `Some(None)` when it has none we can tell.
+    pub(crate) fn of_action(action: &str, params: &Value) -> Option<Option<Target>> {
+        let action = action
+            .trim_end_matches("_async")
+            .trim_end_matches("_rate_limited");
+        if !(action.starts_with("send_") && action.ends_with("_msg")) {
+            return None;
+        }
+        let id = |key: &str| params.get(key).and_then(Value::as_i64);
+        let private = params.get("message_type").and_then(Value::as_str) == Some("private");
+        let target = match (id("group_id").or(id("discuss_id")), id("user_id")) {
+            (Some(group_id), _) if !private && action != "send_private_msg" => Some(Target::Group(group_id)),
+            (_, Some(user_id)) => Some(Target::User(user_id)),
+            _ => None,
+        };
+        Some(target)
+    }
+}
+/// A send waiting for a token, `evict` fires when [`DropPolicy::DropOldest`] drops it.
+struct Waiter {
+    id: u64,
+    target: Option<Target>,
+    evict: oneshot::Sender<()>,
+}
+#[derive(Default)]
+struct State {
+    global: Option<Bucket>,
+    groups: HashMap<i64, Bucket>,
+    users: HashMap<i64, Bucket>,
+    queue: VecDeque<Waiter>,
+}
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    state: Mutex<State>,
+    next_id: AtomicU64,
+    /// Woken whenever a waiter leaves the queue.
+    left: Notify,
+}
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            state: Mutex::default(),
+            next_id: AtomicU64::new(0),
+            left: Notify::new(),
+        }
+    }
+    /// Wait for a token for a message to `target`.
+    ///
+    /// Sends that share a bucket get their tokens in the order they arrived.
+    pub(crate) async fn acquire(&self, target: Option<Target>) -> Result<(), Error> {
+        let mut slot: Option<(QueueSlot, oneshot::Receiver<()>)> = None;
+        loop {
+            let left = self.left.notified();
+            tokio::pin!(left);
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                left.as_mut().enable();
+                let id = slot.as_ref().map(|(slot, _)| slot.id);
+                let behind = state
+                    .queue
+                    .iter()
+                    .take_while(|waiter| Some(waiter.id) != id)
+                    .any(|waiter| self.shares_bucket(waiter.target, target));
+                let wait = if behind {
+                    None
+                } else {
+                    Some(self.try_take(&mut state, target))
+                };
+                if wait == Some(Duration::ZERO) {
+                    return Ok(());
+                }
+                if slot.is_none() {
+                    if state.queue.len() >= self.limit.max_queue {
+                        match self.limit.drop_policy {
+                            DropPolicy::DropNewest => return Err(Error::RateLimited),
+                            DropPolicy::DropOldest => match state.queue.pop_front() {
+                                Some(oldest) => {
+                                    let _ = oldest.evict.send(());
+                                }
+                                None => return Err(Error::RateLimited),
+                            },
+                        }
+                    }
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    let (evict, rx) = oneshot::channel();
+                    state.queue.push_back(Waiter { id, target, evict });
+                    slot = Some((QueueSlot { limiter: self, id }, rx));
+                }
+                wait
+            };
+            let (_, evicted) = slot.as_mut().expect("queued above");
+            match wait {
+                // the oldest send for its buckets, wait for a token
+                Some(wait) => tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = evicted => return Err(Error::RateLimited),
+                },
+                // an older send goes first, wait for it to leave the queue
+                None => tokio::select! {
+                    _ = &mut left => {}
+                    _ = evicted => return Err(Error::RateLimited),
+                },
+            }
+        }
+    }
+    /// Whether sends to `a` and `b` take tokens from a common bucket.
+    fn shares_bucket(&self, a: Option<Target>, b: Option<Target>) -> bool {
+        let keyed = match a {
+            Some(Target::Group(_)) => self.limit.per_group.is_some(),
+            Some(Target::User(_)) => self.limit.per_user.is_some(),
+            None => false,
+        };
+        self.limit.global.is_some() || (keyed && a == b)
+    }
+    /// Take a token from every bucket that applies, or return how long to wait for one.
+    fn try_take(&self, state: &mut State, target: Option<Target>) -> Duration {
+        let now = Instant::now();
+        let limit = &self.limit;
+        let State {
+            global,
+            groups,
+            users,
+            ..
+        } = state;
+        let mut buckets: Vec<(&Budget, &mut Bucket)> = Vec::new();
+        if let Some(budget) = &limit.global {
+            buckets.push((budget, global.get_or_insert_with(|| Bucket::new(budget, now))));
+        }
+        let keyed = match target {
+            Some(Target::Group(id)) => limit.per_group.as_ref().map(|budget| (budget, groups, id)),
+            Some(Target::User(id)) => limit.per_user.as_ref().map(|budget| (budget, users, id)),
+            None => None,
+        };
+        if let Some((budget, map, id)) = keyed {
+            if map.len() > PRUNE_ABOVE {
+                map.retain(|_, bucket| {
+                    bucket.refill(budget, now);
+                    bucket.tokens < budget.burst as f64
+                });
+            }
+            buckets.push((budget, map.entry(id).or_insert_with(|| Bucket::new(budget, now))));
+        }
+        let mut wait = Duration::ZERO;
+        for (budget, bucket) in buckets.iter_mut() {
+            bucket.refill(budget, now);
+            wait = wait.max(bucket.wait(budget));
+        }
+        if wait.is_zero() {
+            buckets.iter_mut().for_each(|(_, bucket)| bucket.tokens -= 1.0);
+        }
+        wait
+    }
+}
+/// Leaves the queue when the waiting send finishes or is cancelled.
+struct QueueSlot<'a> {
+    limiter: &'a RateLimiter,
+    id: u64,
+}
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.queue.retain(|waiter| waiter.id != self.id);
+        self.limiter.left.notify_waiters();
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_target() {
+        let params = serde_json::json!({"group_id":1,"user_id":2});
+        assert_eq!(Target::of_action("send_group_msg_async", &params), Some(Some(Target::Group(1))));
+        assert_eq!(Target::of_action("send_private_msg", &params), Some(Some(Target::User(2))));
+        assert_eq!(Target::of_action("get_msg", &params), None);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimit::new().global(10, 10.0).per_group(1, 1.0).max_queue(1));
+        let start = Instant::now();
+        limiter.acquire(Some(Target::Group(1))).await.unwrap();
+        limiter.acquire(Some(Target::Group(2))).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire(Some(Target::Group(1))).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        let first = limiter.acquire(Some(Target::Group(1)));
+        let second = limiter.acquire(Some(Target::Group(1)));
+        let (first, second) = tokio::join!(first, second);
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::RateLimited)));
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_order() {
+        use futures_util::FutureExt;
+        assert_eq!(RateLimit::default().max_queue, 100);
+        let limiter = RateLimiter::new(RateLimit::new().per_group(1, 1.0));
+        limiter.acquire(Some(Target::Group(1))).await.unwrap();
+        let mut first = Box::pin(limiter.acquire(Some(Target::Group(1))));
+        assert!((&mut first).now_or_never().is_none());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        // the token is back, but `first` has been waiting for it
+        let mut second = Box::pin(limiter.acquire(Some(Target::Group(1))));
+        assert!((&mut second).now_or_never().is_none());
+        assert!(limiter.acquire(Some(Target::Group(2))).now_or_never().is_some());
+        first.await.unwrap();
+        assert!((&mut second).now_or_never().is_none());
+        second.await.unwrap();
+    }
+}
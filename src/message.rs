@@ -1,5 +1,8 @@
 use std::collections::HashMap;
-use serde::{Serialize, Deserialize};
+use std::fmt;
+use serde::{de, Serialize, Deserialize, Deserializer};
+use serde_json::Value;
+use crate::error::ParseError;
 
 /// Escape a str to be used as a CQ code.
 /// `comma` is boolean to control whether to escape comma or not.
@@ -22,12 +25,26 @@ pub fn unescape(s: &str) -> String {
         .replace("&amp;", "&")
      
 }
-#[derive(Default,PartialEq,Debug,Serialize,Deserialize)]
+#[derive(Default,PartialEq,Debug,Clone,Serialize,Deserialize)]
 pub struct MessageSegment{
     #[serde(rename(serialize="type",deserialize="type"))]
     name:String,
+    #[serde(default,deserialize_with="de_data")]
     data:HashMap<String,String>,
 }
+/// Segment data as strings, whatever the implementation sent: `null` is empty, scalars are stringified.
+fn de_data<'de,D:Deserializer<'de>>(deserializer:D)->Result<HashMap<String,String>,D::Error>{
+    let data:Option<HashMap<String,Value>>=Option::deserialize(deserializer)?;
+    let data=data.unwrap_or_default().into_iter().filter_map(|(k,v)|{
+        let v=match v{
+            Value::Null=>return None,
+            Value::String(s)=>s,
+            v=>v.to_string(),
+        };
+        Some((k,v))
+    }).collect();
+    Ok(data)
+}
 impl MessageSegment {
     pub fn new(name:&str,data:Option<HashMap<String,String>>)->MessageSegment{
         MessageSegment{
@@ -132,6 +149,27 @@ impl MessageSegment {
         MessageSegment::new("record",Some(data))
     }
 
+    /// Create a forward node CQ code referring to an existing message.
+    /// `id`:the id of the message to forward.
+    pub fn node(id:i64)->Self{
+        let mut data=HashMap::new();
+        data.insert("id".to_string(),id.to_string());
+        MessageSegment::new("node",Some(data))
+    }
+    /// Create a custom forward node CQ code.
+    /// `name`:the nickname shown for the sender.
+    ///
+    /// `uin`:the qq number shown for the sender.
+    ///
+    /// `content`:the content of the node.
+    pub fn custom_node(name:&str,uin:i64,content:&Message)->Self{
+        let mut data=HashMap::new();
+        data.insert("name".to_string(),name.to_string());
+        data.insert("uin".to_string(),uin.to_string());
+        data.insert("content".to_string(),content.to_string());
+        MessageSegment::new("node",Some(data))
+    }
+
     /// Create a finger-guessing game CQ code.
     pub fn rps()->Self{
         MessageSegment::new("rps",None)
@@ -139,20 +177,24 @@ impl MessageSegment {
 
 
 }
-impl  ToString for MessageSegment{
-    fn to_string(&self) -> String {
+impl fmt::Display for MessageSegment{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.name=="text"{
-            return self.data.get("text").unwrap().to_string()
+            return write!(f,"{}",escape(self.data.get("text").map_or("",String::as_str),false))
         }
         let mut s=format!("[CQ:{}",self.name);
         self.data.iter().for_each(|(k,v)|{
             s.push_str(&format!(",{}",escape(&format!("{}={}",k,v),true)));
         });
         s.push(']');
-        s
+        write!(f,"{}",s)
     }
 }
-#[derive(Default)]
+/// A message, sent in the Onebot array format.
+///
+/// Deserializes from either the array format or a CQ code string.
+#[derive(Default,PartialEq,Debug,Clone,Serialize)]
+#[serde(transparent)]
 pub struct Message{
     segments:Vec<MessageSegment>
 }
@@ -163,18 +205,88 @@ impl Message {
     pub fn append(&mut self,seg:MessageSegment){
         self.segments.push(seg)
     }
+    pub fn segments(&self)->&[MessageSegment]{
+        &self.segments
+    }
+    /// Parse a message in the CQ code string format, the reverse of `to_string`.
+    pub fn from_cq_str(s:&str)->Result<Message,ParseError>{
+        let mut msg=Message::new();
+        let mut text=String::new();
+        let mut rest=s;
+        while !rest.is_empty(){
+            let start=s.len()-rest.len();
+            let Some(code)=rest.strip_prefix("[CQ:") else {
+                let end=rest.find("[CQ:").unwrap_or(rest.len());
+                text.push_str(&rest[..end]);
+                rest=&rest[end..];
+                continue;
+            };
+            let end=code.find(']').ok_or(ParseError::Unclosed(start))?;
+            let mut params=code[..end].split(',');
+            let name=params.next().filter(|name|!name.is_empty()).ok_or(ParseError::MissingType(start))?;
+            let mut data=HashMap::new();
+            for param in params{
+                let (k,v)=param.split_once('=').ok_or(ParseError::MissingValue(start))?;
+                data.insert(unescape(k),unescape(v));
+            }
+            if !text.is_empty(){
+                msg.append(MessageSegment::text(&unescape(&text)));
+                text.clear();
+            }
+            msg.append(MessageSegment::new(&unescape(name),Some(data)));
+            rest=&code[end+1..];
+        }
+        if !text.is_empty(){
+            msg.append(MessageSegment::text(&unescape(&text)));
+        }
+        Ok(msg)
+    }
     
 }
-impl ToString for Message {
-    fn to_string(&self) -> String {
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MessageVisitor;
+        impl<'de> de::Visitor<'de> for MessageVisitor {
+            type Value = Message;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a CQ code string or an array of message segments")
+            }
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Message, E> {
+                Message::from_cq_str(s).map_err(E::custom)
+            }
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Message, A::Error> {
+                let mut msg = Message::new();
+                while let Some(seg) = seq.next_element()? {
+                    msg.append(seg);
+                }
+                Ok(msg)
+            }
+        }
+        deserializer.deserialize_any(MessageVisitor)
+    }
+}
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut msg=String::new();
         self.segments.iter().for_each(|s|{
             msg.push_str(s.to_string().as_str())
         });
-        msg
+        write!(f,"{}",msg)
     }
     
 }
+impl From<MessageSegment> for Message {
+    fn from(value: MessageSegment) -> Self {
+        let mut msg=Message::new();
+        msg.append(value);
+        msg
+    }
+}
+impl From<Vec<MessageSegment>> for Message {
+    fn from(segments: Vec<MessageSegment>) -> Self {
+        Message{segments}
+    }
+}
 impl From<String> for Message {
     fn from(value: String) -> Self {
         let seg=MessageSegment::text(value.as_str());
@@ -191,6 +303,28 @@ impl From<&str> for Message {
         msg
     }
 }
+/// How outgoing messages are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    /// An array of segments.
+    #[default]
+    Array,
+    /// A CQ code string, for implementations that cannot take arrays.
+    String,
+}
+impl MessageFormat {
+    /// Re-encode the `message` of `params` in this format.
+    pub(crate) fn apply(self, params: &mut Value) {
+        if self != MessageFormat::String {
+            return;
+        }
+        if let Some(message) = params.get_mut("message").filter(|message| message.is_array()) {
+            if let Ok(msg) = Message::deserialize(&*message) {
+                *message = Value::String(msg.to_string());
+            }
+        }
+    }
+}
 #[cfg(test)]
 mod test{
     use super::*;
@@ -207,15 +341,49 @@ mod test{
     #[test]
     fn test_image(){
         let code=MessageSegment::image("http://www.baidu.com",Some(true),Some(40000),Some("flash"),Some(1));
-        println!("{}",code.to_string());
+        println!("{}",code);
         let json=serde_json::to_string(&code).unwrap();
         println!("{}",json);
     }
     #[test]
     fn test_record(){
         let code=MessageSegment::record("http://www.baidu.com",Some(true),Some(true),Some(true),Some(10));
-        println!("{}",code.to_string());
+        println!("{}",code);
         let json=serde_json::to_string(&code).unwrap();
         println!("{}",json);
     }
+    #[test]
+    fn test_message_format(){
+        let msg=Message::from(vec![MessageSegment::text("[hi]"),MessageSegment::at(1)]);
+        let mut params=serde_json::json!({"message":msg});
+        MessageFormat::Array.apply(&mut params);
+        assert!(params["message"].is_array());
+        MessageFormat::String.apply(&mut params);
+        assert_eq!(params["message"],"&#91;hi&#93;[CQ:at,qq=1]");
+    }
+    #[test]
+    fn test_from_cq_str(){
+        let mut msg=Message::new();
+        msg.append(MessageSegment::text("a[1],&#91;"));
+        msg.append(MessageSegment::at(10));
+        msg.append(MessageSegment::text("你好"));
+        msg.append(MessageSegment::image("http://a.com/?x=1,y=[2]",None,None,None,None));
+        msg.append(MessageSegment::rps());
+        assert_eq!(Message::from_cq_str(&msg.to_string()).unwrap(),msg);
+        assert_eq!(Message::from_cq_str("[hi]").unwrap(),Message::from("[hi]"));
+        assert_eq!(Message::from_cq_str("a[CQ:at,qq=1"),Err(ParseError::Unclosed(1)));
+        assert_eq!(Message::from_cq_str("[CQ:,qq=1]"),Err(ParseError::MissingType(0)));
+        assert_eq!(Message::from_cq_str("[CQ:at,qq]"),Err(ParseError::MissingValue(0)));
+    }
+    #[test]
+    fn test_lenient_data(){
+        let msg:Message=serde_json::from_str(r#"[{"type":"at","data":{"qq":123}},{"type":"shake","data":null},{"type":"rps"}]"#).unwrap();
+        assert_eq!(msg.segments()[0],MessageSegment::at(123));
+        assert!(msg.segments()[1].data().is_empty());
+        assert_eq!(msg.segments()[2],MessageSegment::rps());
+        let err=serde_json::from_str::<Message>(r#"[{"data":{}}]"#).unwrap_err();
+        assert!(err.to_string().contains("missing field `type`"));
+        let msg:Message=serde_json::from_str(r#"[{"type":"text","data":{}},{"type":"text","data":null}]"#).unwrap();
+        assert_eq!(msg.to_string(),"");
+    }
 }
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// A class of failure a [`RetryPolicy`] can retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    /// The request timed out.
+    Timeout,
+    /// The connection could not be made or broke off.
+    Connection,
+    /// The server answered with a 5xx status, e.g. a reverse proxy without its upstream.
+    ServerError,
+}
+impl RetryOn {
+    fn matches(self, error: &Error) -> bool {
+        match (self, error) {
+            (RetryOn::Timeout, Error::Reqwest(e)) => e.is_timeout(),
+            (RetryOn::Connection, Error::Reqwest(e)) => !e.is_timeout() && (e.is_connect() || e.is_request()),
+            (RetryOn::ServerError, Error::ServerError(_)) => true,
+            _ => false,
+        }
+    }
+}
+/// When and how often [`crate::HttpApi`] retries a failed action, see
+/// [`crate::HttpApiBuilder::retry`].
+///
+/// Only query actions (`get_*`, `can_*`) are retried unless
+/// [`RetryPolicy::retry_non_idempotent`] is set, so a message is never sent twice by accident.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retcodes: Vec<i64>,
+    errors: Vec<RetryOn>,
+    non_idempotent: bool,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            retcodes: Vec::new(),
+            errors: vec![RetryOn::Timeout, RetryOn::Connection, RetryOn::ServerError],
+            non_idempotent: false,
+        }
+    }
+}
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+    /// Wait `initial` before the first retry, doubling up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+    /// Wait a random time between zero and the backoff instead of the full backoff.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    /// Also retry replies with these retcodes, none by default.
+    pub fn retcodes(mut self, retcodes: &[i64]) -> Self {
+        self.retcodes = retcodes.to_vec();
+        self
+    }
+    /// Retry only these failures, all of them by default.
+    pub fn retry_on(mut self, errors: &[RetryOn]) -> Self {
+        self.errors = errors.to_vec();
+        self
+    }
+    /// Retry every action, including the ones that send messages.
+    pub fn retry_non_idempotent(mut self, enable: bool) -> Self {
+        self.non_idempotent = enable;
+        self
+    }
+    pub(crate) fn applies_to(&self, action: &str) -> bool {
+        self.non_idempotent || action.starts_with("get_") || action.starts_with("can_")
+    }
+    /// Whether the outcome of attempt number `attempt` should be retried.
+    pub(crate) fn should_retry(&self, attempt: u32, retcode: Option<i64>, error: Option<&Error>) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        if let Some(retcode) = retcode {
+            return self.retcodes.contains(&retcode);
+        }
+        match error {
+            Some(error) => self.errors.iter().any(|class| class.matches(error)),
+            None => false,
+        }
+    }
+    /// How long to wait after attempt number `attempt` failed.
+    pub(crate) fn backoff_after(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        if self.jitter {
+            backoff.mul_f64(fastrand::f64())
+        } else {
+            backoff
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .jitter(false)
+            .retcodes(&[100]);
+        assert!(policy.applies_to("get_group_list"));
+        assert!(!policy.applies_to("send_group_msg"));
+        assert!(policy.clone().retry_non_idempotent(true).applies_to("send_group_msg"));
+        assert!(policy.should_retry(1, Some(100), None));
+        assert!(!policy.should_retry(1, Some(1404), None));
+        assert!(!policy.should_retry(3, Some(100), None));
+        assert!(policy.should_retry(1, None, Some(&Error::ServerError(503))));
+        let policy = policy.retry_on(&[RetryOn::Timeout]);
+        assert!(!policy.should_retry(1, None, Some(&Error::ServerError(503))));
+        assert_eq!(policy.backoff_after(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_after(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_after(3), Duration::from_millis(300));
+    }
+}
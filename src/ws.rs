@@ -0,0 +1,468 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use log::{log, Level};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        client::IntoClientRequest,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{HeaderValue, StatusCode},
+        Error as WsError, Message as WsMessage,
+    },
+    WebSocketStream,
+};
+
+use crate::{
+    api::{ActionResponse, OneBotApi},
+    bus::EventDispatcher,
+    error::Error,
+    event::get_event,
+    message::MessageFormat,
+};
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+/// The sending half of a websocket that accepts Onebot actions.
+/// Replies are matched to their caller by `echo`.
+#[derive(Clone)]
+pub(crate) struct Connection {
+    sender: mpsc::UnboundedSender<WsMessage>,
+    pending: Pending,
+    echo: Arc<AtomicU64>,
+}
+impl Connection {
+    pub(crate) fn new() -> (Connection, mpsc::UnboundedReceiver<WsMessage>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let conn = Connection {
+            sender,
+            pending: Pending::default(),
+            echo: Arc::new(AtomicU64::new(0)),
+        };
+        (conn, receiver)
+    }
+    pub(crate) async fn call(&self, action: &str, params: Value, timeout: Option<u64>) -> Result<Value, Error> {
+        let echo = self.echo.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(echo, tx);
+        let frame = serde_json::json!({
+            "action":action,
+            "params":params,
+            "echo":echo
+        });
+        if self.sender.send(WsMessage::Text(frame.to_string())).is_err() {
+            self.pending.lock().unwrap().remove(&echo);
+            return Err(Error::NotConnected);
+        }
+        let res = match timeout {
+            Some(timeout) => match tokio::time::timeout(Duration::from_secs(timeout), rx).await {
+                Ok(res) => res,
+                Err(_) => {
+                    self.pending.lock().unwrap().remove(&echo);
+                    return Err(Error::Timeout);
+                }
+            },
+            None => rx.await,
+        };
+        res.map_err(|_| Error::NotConnected)
+    }
+    /// Pump frames between `stream` and the actions queued on this connection until the socket closes.
+    /// Events are handed to `dispatcher`, callers still waiting for a reply get `Error::NotConnected`.
+    pub(crate) async fn drive<S>(
+        &self,
+        stream: WebSocketStream<S>,
+        mut receiver: mpsc::UnboundedReceiver<WsMessage>,
+        dispatcher: Option<&EventDispatcher>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (mut write, mut read) = stream.split();
+        loop {
+            tokio::select! {
+                out = receiver.recv() => match out {
+                    Some(msg) => {
+                        if let Err(e) = write.send(msg).await {
+                            log!(Level::Error, "{}", e);
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                frame = read.next() => match frame {
+                    Some(Ok(WsMessage::Text(text))) => self.handle_frame(&text, dispatcher),
+                    Some(Ok(WsMessage::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        log!(Level::Error, "{}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        self.pending.lock().unwrap().clear();
+    }
+    fn handle_frame(&self, text: &str, dispatcher: Option<&EventDispatcher>) {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => {
+                log!(Level::Error, "{}", e);
+                return;
+            }
+        };
+        if value.get("post_type").is_some() {
+            let Some(dispatcher) = dispatcher else {
+                return;
+            };
+            match get_event(&value) {
+                Ok(event) => {
+                    crate::log_event(&event);
+                    // a panicking handler must not take the connection down with it
+                    if panic::catch_unwind(AssertUnwindSafe(|| dispatcher(event))).is_err() {
+                        log!(Level::Error, "event handler panicked");
+                    }
+                }
+                Err(e) => log!(Level::Error, "{}", e),
+            }
+        } else if let Some(echo) = value.get("echo").and_then(Value::as_u64) {
+            if let Some(tx) = self.pending.lock().unwrap().remove(&echo) {
+                let _ = tx.send(value);
+            }
+        }
+    }
+}
+
+/// The `X-Client-Role` of a reverse websocket connection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ClientRole {
+    Universal,
+    Api,
+    Event,
+}
+
+/// use reverse WebSocket to call Onebot API
+///
+/// Listens on `/ws/`, `/ws/api/` and `/ws/event/` for the Onebot implementation to connect.
+/// Actions are sent to the connection whose `X-Self-ID` matches the `self_id` of the call,
+/// connections without `X-Self-ID` are refused.
+#[derive(Clone, Default)]
+pub struct WebSocketReverseApi {
+    access_token: Option<String>,
+    timeout: Option<u64>,
+    dispatcher: Option<EventDispatcher>,
+    message_format: MessageFormat,
+    connections: Arc<Mutex<HashMap<i64, Connection>>>,
+}
+impl WebSocketReverseApi {
+    pub fn new(access_token: Option<String>, timeout: Option<u64>) -> WebSocketReverseApi {
+        WebSocketReverseApi {
+            access_token,
+            timeout,
+            ..Default::default()
+        }
+    }
+    /// Hand events received on `/ws/` and `/ws/event/` to `dispatcher`.
+    pub fn dispatcher(mut self, dispatcher: EventDispatcher) -> Self {
+        self.dispatcher = Some(dispatcher);
+        self
+    }
+    /// Send messages as CQ code strings with [`MessageFormat::String`], arrays by default.
+    pub fn message_format(mut self, format: MessageFormat) -> Self {
+        self.message_format = format;
+        self
+    }
+    /// Accept connections on `addr` until the listener fails.
+    pub async fn serve(&self, addr: SocketAddr) -> Result<(), Error> {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_listener(listener).await
+    }
+    async fn serve_listener(&self, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let api = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = api.accept(stream).await {
+                    log!(Level::Error, "websocket connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+    // the callback signature is dictated by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn accept(&self, stream: TcpStream) -> Result<(), Error> {
+        let mut client = None;
+        let ws = tokio_tungstenite::accept_hdr_async(stream, |req: &Request, res: Response| {
+            match self.check_handshake(req) {
+                Ok(c) => client = Some(c),
+                Err(status) => {
+                    let mut res = ErrorResponse::new(None);
+                    *res.status_mut() = status;
+                    return Err(res);
+                }
+            }
+            Ok(res)
+        })
+        .await?;
+        let (role, self_id) = client.expect("handshake callback ran");
+        let (conn, receiver) = Connection::new();
+        if role != ClientRole::Event {
+            self.connections.lock().unwrap().insert(self_id, conn.clone());
+        }
+        let dispatcher = if role != ClientRole::Api {
+            self.dispatcher.as_ref()
+        } else {
+            None
+        };
+        conn.drive(ws, receiver, dispatcher).await;
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(current) = connections.get(&self_id) {
+            if Arc::ptr_eq(&current.pending, &conn.pending) {
+                connections.remove(&self_id);
+            }
+        }
+        Ok(())
+    }
+    fn check_handshake(&self, req: &Request) -> Result<(ClientRole, i64), StatusCode> {
+        let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let role = match req.uri().path().trim_end_matches('/') {
+            "/ws" => match header("X-Client-Role") {
+                Some("API") => ClientRole::Api,
+                Some("Event") => ClientRole::Event,
+                _ => ClientRole::Universal,
+            },
+            "/ws/api" => ClientRole::Api,
+            "/ws/event" => ClientRole::Event,
+            _ => return Err(StatusCode::NOT_FOUND),
+        };
+        if let Some(access_token) = &self.access_token {
+            let token = header("Authorization")
+                .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("Token ")))
+                .or_else(|| {
+                    req.uri()
+                        .query()
+                        .and_then(|q| q.split('&').find_map(|kv| kv.strip_prefix("access_token=")))
+                });
+            match token {
+                None => return Err(StatusCode::UNAUTHORIZED),
+                Some(token) if !constant_time_eq(token.as_bytes(), access_token.as_bytes()) => {
+                    return Err(StatusCode::FORBIDDEN)
+                }
+                _ => {}
+            }
+        }
+        let self_id = header("X-Self-ID")
+            .and_then(|v| v.parse().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        Ok((role, self_id))
+    }
+}
+/// Compare two tokens without stopping at the first differing byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+#[async_trait]
+impl OneBotApi for WebSocketReverseApi {
+    async fn call_action(&self, action: &str, mut params: Value) -> Result<ActionResponse, Error> {
+        self.message_format.apply(&mut params);
+        let self_id = params.get("self_id").and_then(Value::as_i64);
+        let conn = {
+            let connections = self.connections.lock().unwrap();
+            match self_id {
+                Some(self_id) => connections.get(&self_id).cloned(),
+                None => connections.values().next().cloned(),
+            }
+        };
+        let conn = conn.ok_or(Error::NotConnected)?;
+        let res = conn.call(action, params, self.timeout).await?;
+        Ok(serde_json::from_value(res)?)
+    }
+}
+/// use forward WebSocket to call Onebot API
+///
+/// Dials the Onebot implementation at `url` and keeps reconnecting with exponential backoff.
+#[derive(Clone)]
+pub struct WebSocketForwardApi {
+    url: String,
+    access_token: Option<String>,
+    timeout: Option<u64>,
+    dispatcher: Option<EventDispatcher>,
+    message_format: MessageFormat,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+impl WebSocketForwardApi {
+    pub fn new(url: &str, access_token: Option<String>, timeout: Option<u64>) -> WebSocketForwardApi {
+        WebSocketForwardApi {
+            url: url.to_string(),
+            access_token,
+            timeout,
+            dispatcher: None,
+            message_format: MessageFormat::default(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            connection: Arc::default(),
+        }
+    }
+    /// Hand events received over the socket to `dispatcher`.
+    pub fn dispatcher(mut self, dispatcher: EventDispatcher) -> Self {
+        self.dispatcher = Some(dispatcher);
+        self
+    }
+    /// Wait `min` before the first reconnect, doubling up to `max` while attempts keep failing.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+    /// Send messages as CQ code strings with [`MessageFormat::String`], arrays by default.
+    pub fn message_format(mut self, format: MessageFormat) -> Self {
+        self.message_format = format;
+        self
+    }
+    /// Connect to `url` and keep the connection alive. Never returns.
+    pub async fn run(&self) {
+        let mut backoff = self.min_backoff;
+        loop {
+            match self.connect().await {
+                Ok(()) => backoff = self.min_backoff,
+                Err(e) => {
+                    log!(Level::Error, "websocket connection to {} failed: {}", self.url, e);
+                }
+            }
+            log!(Level::Warn, "reconnecting to {} in {:?}", self.url, backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+    }
+    async fn connect(&self) -> Result<(), Error> {
+        let mut req = self.url.as_str().into_client_request()?;
+        if let Some(token) = &self.access_token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|e| WsError::HttpFormat(e.into()))?;
+            req.headers_mut().insert("Authorization", value);
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(req).await?;
+        log!(Level::Info, "connected to {}", self.url);
+        let (conn, receiver) = Connection::new();
+        *self.connection.lock().unwrap() = Some(conn.clone());
+        conn.drive(ws, receiver, self.dispatcher.as_ref()).await;
+        self.connection.lock().unwrap().take();
+        Ok(())
+    }
+}
+#[async_trait]
+impl OneBotApi for WebSocketForwardApi {
+    async fn call_action(&self, action: &str, mut params: Value) -> Result<ActionResponse, Error> {
+        self.message_format.apply(&mut params);
+        let conn = self.connection.lock().unwrap().clone();
+        let conn = conn.ok_or(Error::NotConnected)?;
+        let res = conn.call(action, params, self.timeout).await?;
+        Ok(serde_json::from_value(res)?)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::Event;
+    #[tokio::test]
+    async fn test_reverse_ws() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let dispatcher: EventDispatcher = Arc::new(move |event| {
+            event_tx.send(event).unwrap();
+        });
+        let api = WebSocketReverseApi::new(Some("token".to_string()), Some(5)).dispatcher(dispatcher);
+        let server = api.clone();
+        tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let mut req = format!("ws://{}/ws/", addr).into_client_request().unwrap();
+        req.headers_mut().insert("X-Self-ID", "10000".parse().unwrap());
+        req.headers_mut().insert("Authorization", "Bearer token".parse().unwrap());
+        let (mut client, _) = tokio_tungstenite::connect_async(req).await.unwrap();
+        let lifecycle = r#"{"post_type":"meta_event","meta_event_type":"lifecycle","sub_type":"connect","self_id":10000,"time":0}"#;
+        client.send(WsMessage::Text(lifecycle.to_string())).await.unwrap();
+        assert!(matches!(event_rx.recv().await, Some(Event::MetaEvent(_))));
+        let heartbeat = r#"{"post_type":"meta_event","meta_event_type":"heartbeat","self_id":10000,"time":0,"status":{"online":true,"good":true},"interval":5000}"#;
+        client.send(WsMessage::Text(heartbeat.to_string())).await.unwrap();
+        assert!(matches!(event_rx.recv().await, Some(Event::MetaEvent(_))));
+
+        let call = tokio::spawn(async move { api.send_group_msg(123, "hello", false, 10000).await });
+        let frame = client.next().await.unwrap().unwrap();
+        let frame: Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(frame["action"], "send_group_msg");
+        assert_eq!(frame["params"]["group_id"], 123);
+        assert_eq!(frame["params"]["message"][0]["data"]["text"], "hello");
+        let reply = serde_json::json!({"status":"ok","retcode":0,"data":{"message_id":1},"echo":frame["echo"]});
+        client.send(WsMessage::Text(reply.to_string())).await.unwrap();
+        let res = call.await.unwrap().unwrap();
+        assert_eq!(res.data.message_id, 1);
+    }
+    #[tokio::test]
+    async fn test_reverse_ws_rejects_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let api = WebSocketReverseApi::new(Some("token".to_string()), None);
+        tokio::spawn(async move { api.serve_listener(listener).await });
+        let mut req = format!("ws://{}/ws/api/", addr).into_client_request().unwrap();
+        req.headers_mut().insert("X-Self-ID", "10000".parse().unwrap());
+        req.headers_mut().insert("Authorization", "Bearer wrong".parse().unwrap());
+        assert!(tokio_tungstenite::connect_async(req).await.is_err());
+        let mut req = format!("ws://{}/ws/api/", addr).into_client_request().unwrap();
+        req.headers_mut().insert("Authorization", "Bearer token".parse().unwrap());
+        assert!(tokio_tungstenite::connect_async(req).await.is_err());
+    }
+    #[tokio::test]
+    async fn test_forward_ws() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let dispatcher: EventDispatcher = Arc::new(move |event| {
+            event_tx.send(event).unwrap();
+        });
+        let api = WebSocketForwardApi::new(&format!("ws://{}/", addr), None, Some(5))
+            .dispatcher(dispatcher)
+            .backoff(Duration::from_millis(10), Duration::from_millis(10));
+        assert!(matches!(api.get_msg(1, 10000).await, Err(Error::NotConnected)));
+        let client = api.clone();
+        tokio::spawn(async move { client.run().await });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut server = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let bad_notice = r#"{"post_type":"notice","notice_type":"group_ban","self_id":10000,"time":0}"#;
+        server.send(WsMessage::Text(bad_notice.to_string())).await.unwrap();
+        let lifecycle = r#"{"post_type":"meta_event","meta_event_type":"lifecycle","sub_type":"connect","self_id":10000,"time":0}"#;
+        server.send(WsMessage::Text(lifecycle.to_string())).await.unwrap();
+        assert!(matches!(event_rx.recv().await, Some(Event::MetaEvent(_))));
+        let heartbeat = r#"{"post_type":"meta_event","meta_event_type":"heartbeat","self_id":10000,"time":0,"status":{"online":true,"good":true},"interval":5000}"#;
+        server.send(WsMessage::Text(heartbeat.to_string())).await.unwrap();
+        assert!(matches!(event_rx.recv().await, Some(Event::MetaEvent(_))));
+
+        let call = tokio::spawn(async move { api.send_private_msg(1, "hi", false, 10000).await });
+        let frame = server.next().await.unwrap().unwrap();
+        let frame: Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(frame["action"], "send_private_msg");
+        let reply = serde_json::json!({"status":"ok","retcode":0,"data":{"message_id":2},"echo":frame["echo"]});
+        server.send(WsMessage::Text(reply.to_string())).await.unwrap();
+        assert_eq!(call.await.unwrap().unwrap().data.message_id, 2);
+
+        drop(server);
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(tokio_tungstenite::accept_async(stream).await.is_ok());
+    }
+}